@@ -0,0 +1,377 @@
+// property tweening driven by renderer frame time
+// animations hold weak model references and cancel when the model is dropped
+use rust_stddep::nalgebra::UnitQuaternion;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::model::compiled_model::CompiledModel;
+use crate::model::model_ref::ModelRef;
+use crate::{M4, V2, V3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+	Linear,
+	// jump to the end value at the end of the segment
+	Step,
+	QuadIn,
+	QuadOut,
+	QuadInOut,
+	CubicIn,
+	CubicOut,
+	CubicInOut,
+	SineInOut,
+}
+
+impl Default for Easing {
+	fn default() -> Self {
+		Self::Linear
+	}
+}
+
+impl Easing {
+	// t in [0, 1]
+	pub fn apply(self, t: f32) -> f32 {
+		let t = t.clamp(0.0, 1.0);
+		match self {
+			Self::Linear => t,
+			Self::Step => {
+				if t < 1.0 {
+					0.0
+				} else {
+					1.0
+				}
+			}
+			Self::QuadIn => t * t,
+			Self::QuadOut => t * (2.0 - t),
+			Self::QuadInOut => {
+				if t < 0.5 {
+					2.0 * t * t
+				} else {
+					1.0 - 2.0 * (1.0 - t) * (1.0 - t)
+				}
+			}
+			Self::CubicIn => t * t * t,
+			Self::CubicOut => 1.0 - (1.0 - t).powi(3),
+			Self::CubicInOut => {
+				if t < 0.5 {
+					4.0 * t * t * t
+				} else {
+					1.0 - 4.0 * (1.0 - t).powi(3)
+				}
+			}
+			Self::SineInOut => (1.0 - (t * std::f32::consts::PI).cos()) / 2.0,
+		}
+	}
+}
+
+pub trait Lerp: Clone {
+	fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+	fn lerp(&self, other: &Self, t: f32) -> Self {
+		self + (other - self) * t
+	}
+}
+
+impl Lerp for bool {
+	fn lerp(&self, other: &Self, t: f32) -> Self {
+		if t < 1.0 {
+			*self
+		} else {
+			*other
+		}
+	}
+}
+
+impl<const N: usize> Lerp for [f32; N] {
+	fn lerp(&self, other: &Self, t: f32) -> Self {
+		let mut result = *self;
+		for (x, y) in result.iter_mut().zip(other.iter()) {
+			*x = x.lerp(y, t);
+		}
+		result
+	}
+}
+
+impl Lerp for V2 {
+	fn lerp(&self, other: &Self, t: f32) -> Self {
+		self + (other - self) * t
+	}
+}
+
+impl Lerp for V3 {
+	fn lerp(&self, other: &Self, t: f32) -> Self {
+		self + (other - self) * t
+	}
+}
+
+// decomposed model transform, rotation is interpolated by slerp
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+	pub translation: V3,
+	pub rotation: UnitQuaternion<f32>,
+	pub scale: V3,
+}
+
+impl Default for Transform {
+	fn default() -> Self {
+		Self {
+			translation: V3::zeros(),
+			rotation: UnitQuaternion::identity(),
+			scale: V3::new(1.0, 1.0, 1.0),
+		}
+	}
+}
+
+impl Transform {
+	pub fn matrix(&self) -> M4 {
+		M4::new_translation(&self.translation)
+			* self.rotation.to_homogeneous()
+			* M4::new_nonuniform_scaling(&self.scale)
+	}
+}
+
+impl Lerp for Transform {
+	fn lerp(&self, other: &Self, t: f32) -> Self {
+		Self {
+			translation: self.translation.lerp(&other.translation, t),
+			rotation: self.rotation.slerp(&other.rotation, t),
+			scale: self.scale.lerp(&other.scale, t),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+struct Key<T> {
+	time: f32,
+	value: T,
+	// easing of the segment ending at this key
+	easing: Easing,
+}
+
+// keyframes in seconds, starting at 0
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+	keys: Vec<Key<T>>,
+}
+
+impl<T: Lerp> Track<T> {
+	pub fn new(start: T) -> Self {
+		Self {
+			keys: vec![Key {
+				time: 0.0,
+				value: start,
+				easing: Easing::Linear,
+			}],
+		}
+	}
+
+	pub fn tween(from: T, to: T, duration: f32, easing: Easing) -> Self {
+		Self::new(from).then(to, duration, easing)
+	}
+
+	// append a key duration seconds after the last one
+	pub fn then(mut self, value: T, duration: f32, easing: Easing) -> Self {
+		let time = self.duration() + duration.max(0.0);
+		self.keys.push(Key {
+			time,
+			value,
+			easing,
+		});
+		self
+	}
+
+	pub fn duration(&self) -> f32 {
+		self.keys.last().unwrap().time
+	}
+
+	pub fn sample(&self, time: f32) -> T {
+		let idx = self.keys.partition_point(|x| x.time <= time);
+		if idx == 0 {
+			return self.keys[0].value.clone();
+		}
+		if idx == self.keys.len() {
+			return self.keys[idx - 1].value.clone();
+		}
+		let (k0, k1) = (&self.keys[idx - 1], &self.keys[idx]);
+		let t = (time - k0.time) / (k1.time - k0.time);
+		k0.value.lerp(&k1.value, k1.easing.apply(t))
+	}
+}
+
+// plays a track outside the renderer, e.g. camera controllers
+#[derive(Clone, Debug)]
+pub struct Player<T> {
+	track: Track<T>,
+	// none until the first advance
+	time: Option<f32>,
+}
+
+impl<T: Lerp> Player<T> {
+	pub fn new(track: Track<T>) -> Self {
+		Self { track, time: None }
+	}
+
+	// value at the new time and whether the track is finished
+	pub fn advance(&mut self, dt: f32) -> (T, bool) {
+		let time = self.time.map_or(0.0, |x| x + dt);
+		self.time = Some(time);
+		(self.track.sample(time), time >= self.track.duration())
+	}
+}
+
+#[derive(Clone, Debug)]
+pub enum Property {
+	Transform(Track<Transform>),
+	// srgb
+	Tint(Track<[f32; 3]>),
+	Opacity(Track<f32>),
+	Visibility(Track<bool>),
+}
+
+impl Property {
+	fn duration(&self) -> f32 {
+		match self {
+			Self::Transform(x) => x.duration(),
+			Self::Tint(x) => x.duration(),
+			Self::Opacity(x) => x.duration(),
+			Self::Visibility(x) => x.duration(),
+		}
+	}
+
+	fn apply(&self, model: &mut CompiledModel, time: f32) {
+		match self {
+			Self::Transform(x) => {
+				model.transform = x.sample(time).matrix();
+				model.data_dirty = true;
+			}
+			Self::Tint(x) => {
+				model.tint = x.sample(time);
+				model.data_dirty = true;
+			}
+			Self::Opacity(x) => {
+				model.opacity = x.sample(time);
+				model.data_dirty = true;
+			}
+			Self::Visibility(x) => {
+				let visible = x.sample(time);
+				if model.visible != visible {
+					model.visible = visible;
+					model.dirty = true;
+				}
+			}
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+	Once,
+	Loop,
+	PingPong,
+}
+
+impl Default for Repeat {
+	fn default() -> Self {
+		Self::Once
+	}
+}
+
+impl Repeat {
+	// local track time, none if finished
+	pub fn wrap(self, time: f32, duration: f32) -> Option<f32> {
+		if duration <= 0.0 {
+			return match self {
+				Self::Once if time > 0.0 => None,
+				_ => Some(0.0),
+			};
+		}
+		match self {
+			Self::Once if time > duration => None,
+			Self::Once => Some(time),
+			Self::Loop => Some(time % duration),
+			Self::PingPong => {
+				let t = time % (2.0 * duration);
+				Some(if t > duration { 2.0 * duration - t } else { t })
+			}
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AnimId(u64);
+
+struct Anim {
+	id: AnimId,
+	target: Weak<RefCell<CompiledModel>>,
+	property: Property,
+	repeat: Repeat,
+	// none until the first tick, so idle time before start is skipped
+	time: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct Animator {
+	anims: Vec<Anim>,
+	id_alloc: u64,
+}
+
+impl Animator {
+	// the first sample is applied immediately
+	pub fn animate(
+		&mut self,
+		model: &ModelRef,
+		property: Property,
+		repeat: Repeat,
+	) -> AnimId {
+		let id = AnimId(self.id_alloc);
+		self.id_alloc += 1;
+		property.apply(&mut model.borrow_mut(), 0.0);
+		self.anims.push(Anim {
+			id,
+			target: model.downgrade(),
+			property,
+			repeat,
+			time: None,
+		});
+		id
+	}
+
+	pub fn cancel(&mut self, id: AnimId) {
+		self.anims.retain(|x| x.id != id);
+	}
+
+	pub fn is_running(&self, id: AnimId) -> bool {
+		self.anims.iter().any(|x| x.id == id)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.anims.is_empty()
+	}
+
+	// dt in seconds, finished animations end on their last key
+	pub fn tick(&mut self, dt: f32) {
+		self.anims.retain_mut(|anim| {
+			// modelman holds the last strong reference of dropped models
+			let target = match anim.target.upgrade() {
+				Some(x) if Rc::strong_count(&x) > 2 => x,
+				_ => return false,
+			};
+			let time = anim.time.map_or(0.0, |x| x + dt);
+			anim.time = Some(time);
+			let duration = anim.property.duration();
+			let mut model = target.borrow_mut();
+			match anim.repeat.wrap(time, duration) {
+				Some(time) => {
+					anim.property.apply(&mut model, time);
+					true
+				}
+				None => {
+					anim.property.apply(&mut model, duration);
+					false
+				}
+			}
+		});
+	}
+}
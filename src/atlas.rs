@@ -0,0 +1,190 @@
+// packs small images into shared pages,
+// faces using a sprite id as layer sample its region of the page
+use crate::sampler::SamplerOptions;
+use crate::teximg::Teximg;
+
+pub struct Atlas {
+	page_size: u32,
+	// texels around each image, filled with its edge
+	padding: u32,
+	mipmaps: bool,
+	sampler: SamplerOptions,
+	images: Vec<(i32, Teximg)>,
+}
+
+pub struct AtlasPage {
+	pub image: Teximg,
+	// sprite id and uv rect [x, y, w, h] in the page
+	pub sprites: Vec<(i32, [f32; 4])>,
+}
+
+// fills rows of shelves, a shelf is as high as its first image
+struct Shelf {
+	x: u32,
+	y: u32,
+	height: u32,
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+	NegativeId(i32),
+	EmptyImage(i32),
+	// color and srgb must match the first image, a page has one format
+	FormatMismatch(i32),
+	TooLarge(i32),
+}
+
+impl std::fmt::Display for AtlasError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::NegativeId(id) => write!(f, "negative sprite id {}", id),
+			Self::EmptyImage(id) => write!(f, "sprite {} is empty", id),
+			Self::FormatMismatch(id) => {
+				write!(f, "sprite {} differs in color or srgb", id)
+			}
+			Self::TooLarge(id) => {
+				write!(f, "sprite {} is larger than a page", id)
+			}
+		}
+	}
+}
+
+impl std::error::Error for AtlasError {}
+
+impl Atlas {
+	pub fn new(page_size: u32) -> Self {
+		Self {
+			page_size,
+			padding: 1,
+			// mip levels would bleed between sprites
+			mipmaps: false,
+			sampler: Default::default(),
+			images: Vec::new(),
+		}
+	}
+
+	pub fn with_padding(mut self, padding: u32) -> Self {
+		self.padding = padding;
+		self
+	}
+
+	pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+		self.mipmaps = mipmaps;
+		self
+	}
+
+	// repeat address modes do not work on sprites
+	pub fn with_sampler(mut self, sampler: SamplerOptions) -> Self {
+		self.sampler = sampler;
+		self
+	}
+
+	// sprite ids share the namespace of texture ids
+	pub fn add(&mut self, id: i32, image: Teximg) -> Result<(), AtlasError> {
+		if id < 0 {
+			return Err(AtlasError::NegativeId(id));
+		}
+		if image.dim[0] == 0 || image.dim[1] == 0 {
+			return Err(AtlasError::EmptyImage(id));
+		}
+		if let Some((_, first)) = self.images.first() {
+			if image.color != first.color || image.srgb != first.srgb {
+				return Err(AtlasError::FormatMismatch(id));
+			}
+		}
+		let cell = image.dim.map(|x| x + 2 * self.padding);
+		if cell[0] > self.page_size || cell[1] > self.page_size {
+			return Err(AtlasError::TooLarge(id));
+		}
+		self.images.push((id, image));
+		Ok(())
+	}
+
+	pub fn pack(mut self) -> Vec<AtlasPage> {
+		if self.images.is_empty() {
+			return Vec::new();
+		}
+		// tall images first keeps shelves tight
+		self.images.sort_by_key(|(_, x)| std::cmp::Reverse(x.dim[1]));
+		let (color, srgb) = (self.images[0].1.color, self.images[0].1.srgb);
+		let mut pages = Vec::new();
+		let mut page = self.new_page(color, srgb);
+		let mut shelf = Shelf {
+			x: 0,
+			y: 0,
+			height: 0,
+		};
+		let images = std::mem::take(&mut self.images);
+		for (id, image) in images.into_iter() {
+			let [w, h] = image.dim.map(|x| x + 2 * self.padding);
+			if shelf.x + w > self.page_size {
+				shelf = Shelf {
+					x: 0,
+					y: shelf.y + shelf.height,
+					height: 0,
+				};
+			}
+			if shelf.y + h > self.page_size {
+				pages.push(page);
+				page = self.new_page(color, srgb);
+				shelf = Shelf {
+					x: 0,
+					y: 0,
+					height: 0,
+				};
+			}
+			self.blit(&mut page.image, &image, [shelf.x, shelf.y]);
+			let size = self.page_size as f32;
+			page.sprites.push((
+				id,
+				[
+					(shelf.x + self.padding) as f32 / size,
+					(shelf.y + self.padding) as f32 / size,
+					image.dim[0] as f32 / size,
+					image.dim[1] as f32 / size,
+				],
+			));
+			shelf.x += w;
+			shelf.height = shelf.height.max(h);
+		}
+		pages.push(page);
+		pages
+	}
+
+	fn new_page(&self, color: bool, srgb: bool) -> AtlasPage {
+		let channels = if color { 4 } else { 1 };
+		let len = (self.page_size * self.page_size) as usize * channels;
+		let image = Teximg {
+			color,
+			srgb,
+			mipmaps: self.mipmaps,
+			dynamic: false,
+			evictable: false,
+			sampler: self.sampler,
+			dim: [self.page_size; 2],
+			data: vec![0; len],
+		};
+		AtlasPage {
+			image,
+			sprites: Vec::new(),
+		}
+	}
+
+	// copy into the cell at origin, padding repeats the edge texels
+	fn blit(&self, page: &mut Teximg, image: &Teximg, origin: [u32; 2]) {
+		let channels = image.channels();
+		let p = self.padding as i64;
+		let [w, h] = image.dim;
+		for y in 0..h + 2 * self.padding {
+			let sy = (y as i64 - p).clamp(0, h as i64 - 1) as u32;
+			for x in 0..w + 2 * self.padding {
+				let sx = (x as i64 - p).clamp(0, w as i64 - 1) as u32;
+				let src = (sy * w + sx) as usize * channels;
+				let dst = ((origin[1] + y) * self.page_size + origin[0] + x)
+					as usize * channels;
+				page.data[dst..dst + channels]
+					.copy_from_slice(&image.data[src..src + channels]);
+			}
+		}
+	}
+}
@@ -8,11 +8,14 @@ use vulkano::device::physical::PhysicalDeviceType;
 use vulkano::device::{
 	Device, DeviceCreateInfo, DeviceExtensions, Features, QueueCreateInfo,
 };
+use vulkano::format::Format;
 use vulkano::image::ImageUsage;
 use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
 use vulkano::memory::allocator::StandardMemoryAllocator;
 use vulkano::swapchain::{
-	PresentMode, Swapchain, SwapchainCreateInfo, Surface, SurfaceCreationError};
+	ColorSpace, PresentMode, Swapchain, SwapchainCreateInfo, Surface,
+	SurfaceCreationError,
+};
 use vulkano::{Version, VulkanLibrary};
 
 use crate::helper::*;
@@ -25,6 +28,7 @@ pub struct Base {
 	pub surface: VkwSurface,
 	pub swapchain: VkwSwapchain,
 	pub images: VkwImages,
+	pub depth_format: Format,
 	pub memalloc: VkwMemAlloc,
 	pub dstalloc: VkwDstAlloc,
 	pub comalloc: VkwComAlloc,
@@ -114,10 +118,11 @@ impl Base {
 			get_device_and_queue(&instance, surface.clone());
 
 		let (swapchain, images) = get_swapchain_and_images(
-			physical_device,
+			physical_device.clone(),
 			device.clone(),
 			surface.clone(),
 		);
+		let depth_format = get_depth_format(&physical_device);
 		let (memalloc, dstalloc, comalloc) = get_allocators(device.clone());
 		Self {
 			instance,
@@ -126,6 +131,7 @@ impl Base {
 			surface,
 			swapchain,
 			images,
+			depth_format,
 			memalloc,
 			dstalloc,
 			comalloc,
@@ -191,6 +197,13 @@ pub fn get_device_and_queue(
 	//	physical_device.properties().device_type,
 	// );
 
+	// optional, samplers fall back to no anisotropy
+	let features = Features {
+		sampler_anisotropy: physical_device
+			.supported_features()
+			.sampler_anisotropy,
+		..features
+	};
 	let (device, mut queues) = Device::new(
 		physical_device.clone(),
 		DeviceCreateInfo {
@@ -210,6 +223,42 @@ pub fn get_device_and_queue(
 	(physical_device, device, queue)
 }
 
+// no depth-stencil format is mandatory, take the first supported one
+pub fn get_depth_format(physical_device: &VkwPhysicalDevice) -> Format {
+	[
+		Format::D24_UNORM_S8_UINT,
+		Format::D32_SFLOAT_S8_UINT,
+		Format::D16_UNORM_S8_UINT,
+	]
+	.into_iter()
+	.find(|format| {
+		physical_device
+			.format_properties(*format)
+			.optimal_tiling_features
+			.depth_stencil_attachment
+	})
+	.expect("No depth stencil format supported")
+}
+
+// blending is done in linear space and encoded by the swapchain,
+// fallback to the first format and encode in fragment shader if no srgb
+pub fn pick_surface_format(formats: &[(Format, ColorSpace)]) -> Format {
+	formats
+		.iter()
+		.find(|(f, cs)| srgb_format(*f) && *cs == ColorSpace::SrgbNonLinear)
+		.unwrap_or(&formats[0])
+		.0
+}
+
+pub fn srgb_format(format: Format) -> bool {
+	matches!(
+		format,
+		Format::B8G8R8A8_SRGB
+			| Format::R8G8B8A8_SRGB
+			| Format::A8B8G8R8_SRGB_PACK32
+	)
+}
+
 pub fn get_swapchain_and_images(
 	physical_device: VkwPhysicalDevice,
 	device: VkwDevice,
@@ -219,11 +268,10 @@ pub fn get_swapchain_and_images(
 		.surface_capabilities(&surface, Default::default())
 		.unwrap();
 	let composite_alpha = caps.supported_composite_alpha.iter().next().unwrap();
-	let format = physical_device
+	let formats = physical_device
 		.surface_formats(&surface, Default::default())
-		.unwrap()[0]
-		.0;
-	let format = Some(format);
+		.unwrap();
+	let format = Some(pick_surface_format(&formats));
 	let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
 	let dimensions: [u32; 2] = window.inner_size().into();
 
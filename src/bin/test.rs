@@ -4,6 +4,7 @@ use ttri::reexport::winit::{
 };
 
 use ttri::cam::camcon3::Camcon;
+use ttri::model::model_ref::ModelRef;
 use ttri::teximg::Teximg;
 use ttri::renderer::Renderer;
 use ttri_model::cmodel::{Model, Face};
@@ -15,7 +16,7 @@ fn main() {
 	let mut rdr = Renderer::new(&el);
 	let tex = Teximg::preset_rgb565();
 	let mut camcon = Camcon::new(V3::new(1.0, 1.0, -10.0));
-	let mut _mh = Vec::new();
+	let mut mh: Option<ModelRef> = None;
 	rdr.upload_tex(tex, 0);
 	el.run(move |event, _, ctrl| match event {
 		Event::WindowEvent { event: e, .. } => {
@@ -73,9 +74,16 @@ fn main() {
 				},
 			];
 			let model = Model {vs, uvs, faces};
-			_mh = vec![rdr.insert_model(&model)];
+			match mh.as_mut() {
+				Some(mh) => mh.update(&model),
+				None => mh = Some(rdr.insert_model(&model)),
+			}
 			let cam = camcon.get_camera();
 			rdr.render_p(cam);
+			// uploads become visible in a later frame
+			if rdr.uploads_pending() {
+				rdr.redraw();
+			}
 			*ctrl = ControlFlow::Wait;
 		}
 		_ => {},
@@ -1,5 +1,6 @@
 use rust_stddep::winit::event::{ElementState, MouseButton, WindowEvent, VirtualKeyCode, KeyboardInput};
 
+use crate::anim::{Easing, Player, Track};
 use crate::{V2, M4};
 
 // 2d camera controller
@@ -8,6 +9,8 @@ pub struct Camcon {
 	screen_r: V2, // e.g. (960.0, 540.0)
 	zoom: f32,
 
+	// center x, center y, ln zoom
+	tween: Option<Player<[f32; 3]>>,
 	control_state: ControlState,
 }
 
@@ -26,6 +29,7 @@ impl Camcon {
 			screen_r: r,
 			zoom: 1.0,
 
+			tween: None,
 			control_state: Default::default(),
 		}
 	}
@@ -56,6 +60,36 @@ impl Camcon {
 		self.zoom *= k;
 	}
 
+	// zoom is interpolated in log space, user input cancels the tween
+	pub fn animate_to(
+		&mut self,
+		center: V2,
+		zoom: f32,
+		duration: f32,
+		easing: Easing,
+	) {
+		let c = self.world_center;
+		let from = [c[0], c[1], self.zoom.ln()];
+		let to = [center[0], center[1], zoom.ln()];
+		let track = Track::tween(from, to, duration, easing);
+		self.tween = Some(Player::new(track));
+	}
+
+	// dt in seconds, e.g. Renderer::frame_time, true if camera changed
+	pub fn tick(&mut self, dt: f32) -> bool {
+		let tween = match self.tween.as_mut() {
+			Some(x) => x,
+			None => return false,
+		};
+		let ([cx, cy, zoom], finished) = tween.advance(dt);
+		self.world_center = V2::new(cx, cy);
+		self.zoom = zoom.exp();
+		if finished {
+			self.tween = None;
+		}
+		true
+	}
+
 	pub fn get_camera(&self) -> M4 {
 		let [cx, cy]: [f32; 2] = self.world_center.into();
 		let [rx, ry]: [f32; 2] =
@@ -122,6 +156,9 @@ impl Camcon {
 			}
 			_ => {}
 		}
+		if result {
+			self.tween = None;
+		}
 		result
 	}
 }
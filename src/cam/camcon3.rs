@@ -1,5 +1,6 @@
 use rust_stddep::winit::event::{ElementState, MouseButton, WindowEvent};
 
+use crate::anim::{Easing, Player, Track};
 use crate::{V2, V3, V4, M4};
 
 // 3d camera controller
@@ -9,6 +10,8 @@ pub struct Camcon {
 	yaw: f32,
 	pitch: f32,
 
+	// x, y, z, yaw, pitch
+	tween: Option<Player<[f32; 5]>>,
 	control_state: ControlState,
 }
 
@@ -27,10 +30,43 @@ impl Camcon {
 			yaw: 0.0,
 			pitch: 0.0,
 
+			tween: None,
 			control_state: Default::default(),
 		}
 	}
 
+	// user input cancels the tween
+	pub fn animate_to(
+		&mut self,
+		pos: V3,
+		yaw: f32,
+		pitch: f32,
+		duration: f32,
+		easing: Easing,
+	) {
+		let p = self.pos;
+		let from = [p[0], p[1], p[2], self.yaw, self.pitch];
+		let to = [pos[0], pos[1], pos[2], yaw, pitch];
+		let track = Track::tween(from, to, duration, easing);
+		self.tween = Some(Player::new(track));
+	}
+
+	// dt in seconds, e.g. Renderer::frame_time, true if camera changed
+	pub fn tick(&mut self, dt: f32) -> bool {
+		let tween = match self.tween.as_mut() {
+			Some(x) => x,
+			None => return false,
+		};
+		let ([x, y, z, yaw, pitch], finished) = tween.advance(dt);
+		self.pos = V3::new(x, y, z);
+		self.yaw = yaw;
+		self.pitch = pitch;
+		if finished {
+			self.tween = None;
+		}
+		true
+	}
+
 	pub fn get_camera(&self) -> M4 {
 		self.get_trans().prepend_translation(&self.pos)
 	}
@@ -102,6 +138,9 @@ impl Camcon {
 			}
 			_ => {}
 		}
+		if result {
+			self.tween = None;
+		}
 		result
 	}
 }
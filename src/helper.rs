@@ -8,14 +8,15 @@ use vulkano::descriptor_set::layout::DescriptorSetLayout;
 use vulkano::descriptor_set::PersistentDescriptorSet;
 use vulkano::device::physical::PhysicalDevice;
 use vulkano::device::{Device, Queue};
-use vulkano::image::view::ImageView;
-use vulkano::image::{ImmutableImage, SwapchainImage};
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{AttachmentImage, SwapchainImage};
 use vulkano::instance::Instance;
 use vulkano::memory::allocator::StandardMemoryAllocator;
-use vulkano::pipeline::GraphicsPipeline;
+use vulkano::pipeline::{GraphicsPipeline, PipelineLayout};
 use vulkano::render_pass::{Framebuffer, RenderPass};
+use vulkano::shader::ShaderModule;
 use vulkano::swapchain::{Surface, Swapchain};
-use vulkano::sync::GpuFuture;
+use vulkano::sync::{FenceSignalFuture, GpuFuture};
 
 pub type VkwCommandBuilder = AutoCommandBufferBuilder<
 	PrimaryAutoCommandBuffer,
@@ -25,15 +26,20 @@ pub type VkwPhysicalDevice = Arc<PhysicalDevice>;
 pub type VkwDevice = Arc<Device>;
 pub type VkwFramebuffer = Arc<Framebuffer>;
 pub type VkwFuture = Box<dyn GpuFuture>;
-pub type VkwImageView = Arc<ImageView<ImmutableImage>>;
+pub type VkwFence = Arc<FenceSignalFuture<VkwFuture>>;
+pub type VkwImageView = Arc<dyn ImageViewAbstract>;
+pub type VkwAttachmentView = Arc<ImageView<AttachmentImage>>;
 pub type VkwImages = Vec<Arc<SwapchainImage>>;
 pub type VkwInstance = Arc<Instance>;
 pub type VkwPipeline = Arc<GraphicsPipeline>;
+pub type VkwPipelineLayout = Arc<PipelineLayout>;
 pub type VkwQueue = Arc<Queue>;
 pub type VkwRenderPass = Arc<RenderPass>;
+pub type VkwShader = Arc<ShaderModule>;
 pub type VkwSurface = Arc<Surface>;
 pub type VkwSwapchain = Arc<Swapchain>;
 pub type VkwTextureSet = Arc<PersistentDescriptorSet>;
+pub type VkwDescSet = Arc<PersistentDescriptorSet>;
 pub type VkwTexLayout = Arc<DescriptorSetLayout>;
 pub type VkwMemAlloc = Arc<StandardMemoryAllocator>;
 pub type VkwDstAlloc = Arc<StandardDescriptorSetAllocator>;
@@ -1,14 +1,26 @@
+pub mod anim;
+pub mod atlas;
 pub mod cam;
+pub mod loader;
 pub mod model;
+pub mod raycast;
 pub mod renderer;
+pub mod sampler;
+pub mod shaderman;
 pub mod teximg;
+pub mod texture_ref;
 
 mod base;
 mod camera;
 mod helper;
+mod model_data;
+mod oit;
+mod outline;
+mod picking;
 mod rmod;
 mod texman;
 mod vertex;
+mod watcher;
 
 
 pub type V2 = rust_stddep::nalgebra::Vector2<f32>;
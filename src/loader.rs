@@ -0,0 +1,161 @@
+// images are decoded on worker threads,
+// renderer uploads finished ones in one batch per frame
+use image::ImageError;
+use std::collections::HashMap;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::teximg::Teximg;
+
+// textures uploaded per frame at most
+pub const LOAD_BATCH: usize = 16;
+
+pub type Configure = Box<dyn FnOnce(Teximg) -> Teximg + Send>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+	pub pending: usize,
+	pub loaded: usize,
+	pub failed: usize,
+}
+
+struct Job {
+	id: i32,
+	// a newer request of the same id discards older results
+	generation: u64,
+	path: PathBuf,
+	color: bool,
+	configure: Configure,
+}
+
+type Loaded = (i32, u64, Result<Teximg, ImageError>);
+
+pub(crate) struct Loader {
+	// none until the first request spawns workers
+	jobs: Option<Sender<Job>>,
+	results_tx: Sender<Loaded>,
+	results: Receiver<Loaded>,
+	// id to generation of the latest request
+	pending: HashMap<i32, u64>,
+	generation: u64,
+	loaded: usize,
+	failed: usize,
+	pub errors: Vec<(i32, ImageError)>,
+}
+
+impl Default for Loader {
+	fn default() -> Self {
+		let (results_tx, results) = mpsc::channel();
+		Self {
+			jobs: None,
+			results_tx,
+			results,
+			pending: HashMap::new(),
+			generation: 0,
+			loaded: 0,
+			failed: 0,
+			errors: Vec::new(),
+		}
+	}
+}
+
+fn spawn_workers(results: Sender<Loaded>) -> Sender<Job> {
+	let (tx, rx) = mpsc::channel::<Job>();
+	let rx = Arc::new(Mutex::new(rx));
+	let workers = thread::available_parallelism().map_or(1, |x| x.get());
+	for _ in 0..workers.min(4) {
+		let rx = rx.clone();
+		let results = results.clone();
+		thread::spawn(move || loop {
+			// exit when the loader is dropped
+			let job = match rx.lock().unwrap().recv() {
+				Ok(job) => job,
+				Err(_) => break,
+			};
+			let (path, color, configure) = (job.path, job.color, job.configure);
+			// a panicking decoder or configure fails only this id
+			let image = panic::catch_unwind(AssertUnwindSafe(|| {
+				Teximg::try_load(&path, color).map(configure)
+			}))
+			.unwrap_or_else(|_| {
+				let msg = format!("loading {} panicked", path.display());
+				Err(ImageError::IoError(io::Error::new(
+					io::ErrorKind::Other,
+					msg,
+				)))
+			});
+			if results.send((job.id, job.generation, image)).is_err() {
+				break;
+			}
+		});
+	}
+	tx
+}
+
+impl Loader {
+	pub fn load(
+		&mut self,
+		path: PathBuf,
+		id: i32,
+		color: bool,
+		configure: Configure,
+	) {
+		self.generation += 1;
+		self.pending.insert(id, self.generation);
+		let results = &self.results_tx;
+		let jobs = self
+			.jobs
+			.get_or_insert_with(|| spawn_workers(results.clone()));
+		let job = Job {
+			id,
+			generation: self.generation,
+			path,
+			color,
+			configure,
+		};
+		jobs.send(job).unwrap();
+	}
+
+	// the result is discarded when it arrives
+	pub fn cancel(&mut self, id: i32) {
+		self.pending.remove(&id);
+	}
+
+	pub fn progress(&self) -> LoadProgress {
+		LoadProgress {
+			pending: self.pending.len(),
+			loaded: self.loaded,
+			failed: self.failed,
+		}
+	}
+
+	// finished images to upload, errors are kept in self.errors
+	pub fn poll(&mut self, max: usize) -> Vec<(i32, Teximg)> {
+		let mut result = Vec::new();
+		while result.len() < max {
+			let (id, generation, image) = match self.results.try_recv() {
+				Ok(x) => x,
+				Err(_) => break,
+			};
+			if self.pending.get(&id) != Some(&generation) {
+				continue;
+			}
+			self.pending.remove(&id);
+			match image {
+				Ok(image) => {
+					self.loaded += 1;
+					result.push((id, image));
+				}
+				Err(e) => {
+					self.failed += 1;
+					self.errors.push((id, e));
+				}
+			}
+		}
+		result
+	}
+}
@@ -0,0 +1,63 @@
+use vulkano::pipeline::graphics::color_blend::{
+	AttachmentBlend, BlendFactor, BlendOp,
+};
+
+// fragment output is linear straight alpha, except for premultiplied
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+	Opaque,
+	Alpha,
+	Premultiplied,
+	Additive,
+	Multiply,
+	Screen,
+}
+
+impl Default for BlendMode {
+	fn default() -> Self {
+		Self::Alpha
+	}
+}
+
+fn blend(
+	color_source: BlendFactor,
+	color_destination: BlendFactor,
+	alpha_source: BlendFactor,
+	alpha_destination: BlendFactor,
+) -> AttachmentBlend {
+	AttachmentBlend {
+		color_op: BlendOp::Add,
+		color_source,
+		color_destination,
+		alpha_op: BlendOp::Add,
+		alpha_source,
+		alpha_destination,
+	}
+}
+
+impl BlendMode {
+	pub fn attachment_blend(self) -> Option<AttachmentBlend> {
+		use BlendFactor as F;
+		Some(match self {
+			Self::Opaque => return None,
+			Self::Alpha => blend(
+				F::SrcAlpha,
+				F::OneMinusSrcAlpha,
+				F::One,
+				F::OneMinusSrcAlpha,
+			),
+			Self::Premultiplied => blend(
+				F::One,
+				F::OneMinusSrcAlpha,
+				F::One,
+				F::OneMinusSrcAlpha,
+			),
+			// destination alpha is kept for the following three
+			Self::Additive => blend(F::SrcAlpha, F::One, F::Zero, F::One),
+			Self::Multiply => blend(F::DstColor, F::Zero, F::Zero, F::One),
+			Self::Screen => {
+				blend(F::One, F::OneMinusSrcColor, F::Zero, F::One)
+			}
+		})
+	}
+}
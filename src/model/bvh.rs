@@ -0,0 +1,202 @@
+// bounding volume hierarchy over the triangles of a compiled model
+use crate::vertex::VertexTex;
+use crate::V3;
+
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Aabb {
+	min: V3,
+	max: V3,
+}
+
+impl Aabb {
+	fn empty() -> Self {
+		Self {
+			min: V3::repeat(f32::INFINITY),
+			max: V3::repeat(f32::NEG_INFINITY),
+		}
+	}
+
+	fn grow(&mut self, p: &V3) {
+		self.min = self.min.inf(p);
+		self.max = self.max.sup(p);
+	}
+
+	// slab test, returns entry distance
+	fn hit(&self, origin: &V3, inv_dir: &V3, max_t: f32) -> Option<f32> {
+		let mut t0 = 0f32;
+		let mut t1 = max_t;
+		for axis in 0..3 {
+			let a = (self.min[axis] - origin[axis]) * inv_dir[axis];
+			let b = (self.max[axis] - origin[axis]) * inv_dir[axis];
+			t0 = t0.max(a.min(b));
+			t1 = t1.min(a.max(b));
+		}
+		if t0 <= t1 {
+			Some(t0)
+		} else {
+			None
+		}
+	}
+}
+
+enum Node {
+	Leaf { aabb: Aabb, first: usize, count: usize },
+	Branch { aabb: Aabb, left: usize, right: usize },
+}
+
+impl Node {
+	fn aabb(&self) -> &Aabb {
+		match self {
+			Node::Leaf { aabb, .. } => aabb,
+			Node::Branch { aabb, .. } => aabb,
+		}
+	}
+}
+
+pub struct Bvh {
+	nodes: Vec<Node>,
+	// triangle indices, leaves refer to ranges of this
+	tris: Vec<usize>,
+	// triangle corners in world space
+	corners: Vec<[V3; 3]>,
+}
+
+// triangle index, barycentric (of corner 1 and 2) and distance
+pub struct BvhHit {
+	pub tri: usize,
+	pub uv: [f32; 2],
+	pub t: f32,
+}
+
+pub fn vertex_pos(v: &VertexTex) -> V3 {
+	let [x, y, z, w] = v.pos;
+	if w == 0.0 {
+		V3::new(x, y, z)
+	} else {
+		V3::new(x / w, y / w, z / w)
+	}
+}
+
+// moller-trumbore, two sided
+fn hit_triangle(
+	[a, b, c]: &[V3; 3],
+	origin: &V3,
+	dir: &V3,
+) -> Option<(f32, [f32; 2])> {
+	let e1 = b - a;
+	let e2 = c - a;
+	let p = dir.cross(&e2);
+	let det = e1.dot(&p);
+	if det.abs() < f32::EPSILON {
+		return None;
+	}
+	let inv_det = 1.0 / det;
+	let s = origin - a;
+	let u = s.dot(&p) * inv_det;
+	if !(0.0..=1.0).contains(&u) {
+		return None;
+	}
+	let q = s.cross(&e1);
+	let v = dir.dot(&q) * inv_det;
+	if v < 0.0 || u + v > 1.0 {
+		return None;
+	}
+	let t = e2.dot(&q) * inv_det;
+	if t < 0.0 {
+		return None;
+	}
+	Some((t, [u, v]))
+}
+
+impl Bvh {
+	pub fn new(vertices: &[VertexTex]) -> Self {
+		let corners: Vec<[V3; 3]> = vertices
+			.chunks_exact(3)
+			.map(|x| {
+				[vertex_pos(&x[0]), vertex_pos(&x[1]), vertex_pos(&x[2])]
+			})
+			.collect();
+		let mut result = Self {
+			nodes: Vec::new(),
+			tris: (0..corners.len()).collect(),
+			corners,
+		};
+		if !result.tris.is_empty() {
+			result.build(0, result.tris.len());
+		}
+		result
+	}
+
+	fn centroid(&self, tri: usize) -> V3 {
+		let [a, b, c] = &self.corners[tri];
+		(a + b + c) / 3.0
+	}
+
+	// returns node index
+	fn build(&mut self, first: usize, count: usize) -> usize {
+		let mut aabb = Aabb::empty();
+		let mut centers = Aabb::empty();
+		for &tri in self.tris[first..first + count].iter() {
+			for p in self.corners[tri].iter() {
+				aabb.grow(p);
+			}
+			centers.grow(&self.centroid(tri));
+		}
+		let idx = self.nodes.len();
+		if count <= LEAF_SIZE {
+			self.nodes.push(Node::Leaf { aabb, first, count });
+			return idx;
+		}
+		// median split on the longest axis of centroids
+		let extent = centers.max - centers.min;
+		let axis = extent.imax();
+		let mut tris = std::mem::take(&mut self.tris);
+		tris[first..first + count].sort_by(|a, b| {
+			self.centroid(*a)[axis].total_cmp(&self.centroid(*b)[axis])
+		});
+		self.tris = tris;
+		// placeholder, replaced after children are built
+		self.nodes.push(Node::Leaf { aabb, first, count });
+		let half = count / 2;
+		let left = self.build(first, half);
+		let right = self.build(first + half, count - half);
+		self.nodes[idx] = Node::Branch { aabb, left, right };
+		idx
+	}
+
+	pub fn raycast(&self, origin: &V3, dir: &V3) -> Option<BvhHit> {
+		if self.nodes.is_empty() {
+			return None;
+		}
+		let inv_dir = dir.map(|x| 1.0 / x);
+		let mut best: Option<BvhHit> = None;
+		let mut stack = vec![0];
+		while let Some(idx) = stack.pop() {
+			let max_t = best.as_ref().map_or(f32::INFINITY, |x| x.t);
+			let node = &self.nodes[idx];
+			if node.aabb().hit(origin, &inv_dir, max_t).is_none() {
+				continue;
+			}
+			match node {
+				Node::Leaf { first, count, .. } => {
+					for &tri in self.tris[*first..first + count].iter() {
+						let hit =
+							hit_triangle(&self.corners[tri], origin, dir);
+						if let Some((t, uv)) = hit {
+							if best.as_ref().map_or(true, |x| t < x.t) {
+								best = Some(BvhHit { tri, uv, t });
+							}
+						}
+					}
+				}
+				Node::Branch { left, right, .. } => {
+					stack.push(*left);
+					stack.push(*right);
+				}
+			}
+		}
+		best
+	}
+}
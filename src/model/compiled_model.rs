@@ -1,7 +1,121 @@
+use std::ops::Range;
+
+use super::blend_mode::BlendMode;
+use super::bvh::Bvh;
+use super::mask_mode::MaskMode;
+use super::morph::MorphDelta;
+use super::skin::SkinVertex;
+use crate::model_data::{ModelData, PushData};
+use crate::shaderman::ShaderId;
 use crate::vertex::VertexTex;
+use crate::M4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Outline {
+	// srgb like face colors
+	pub color: [f32; 4],
+	// in pixels
+	pub width: f32,
+}
 
 pub struct CompiledModel {
+	// unique among live models, written to picking attachment
+	pub id: u32,
 	pub visible: bool,
 	pub z: i32,
+	pub blend: BlendMode,
+	// sorted back to front and drawn without depth write
+	pub translucent: bool,
+	pub mask: MaskMode,
+	pub outline: Option<Outline>,
+	pub shader: ShaderId,
+	pub push: PushData,
+	// model to world
+	pub transform: M4,
+	// srgb
+	pub tint: [f32; 3],
+	pub opacity: f32,
+	// rows of 2x3 affine matrix applied to tex coords
+	pub uv_transform: [[f32; 3]; 2],
+	// target major deltas, see morph.rs
+	pub morph: Vec<MorphDelta>,
+	pub morph_vertices: u32,
+	pub morph_weights: Vec<f32>,
+	pub skin: Vec<SkinVertex>,
+	pub inverse_bind: Vec<M4>,
+	// pose times inverse bind, one per joint
+	pub joint_matrices: Vec<M4>,
+	// assigned by modelman when the deform buffers are rebuilt
+	pub morph_offset: u32,
+	pub skin_offset: u32,
+	// morph or skin data changed
+	pub deform_dirty: bool,
 	pub vertices: Vec<VertexTex>,
+	// source face index of each triangle, invalid faces are skipped
+	pub faces: Vec<usize>,
+	// built on first raycast
+	pub bvh: Option<Bvh>,
+	// distinct texture slots of vertices, none = stale
+	pub tex_slots: Option<Vec<i32>>,
+	// slots acquired at compile, released on recompile or drop
+	pub slot_refs: Vec<i32>,
+	// draw state changed, modelman should rebuild draw list
+	pub dirty: bool,
+	// only model data changed, no vertex upload needed
+	pub data_dirty: bool,
+	// vertices changed in place, count is unchanged
+	pub vertices_dirty: Option<Range<usize>>,
+}
+
+impl CompiledModel {
+	pub fn bvh(&mut self) -> &Bvh {
+		if self.bvh.is_none() {
+			self.bvh = Some(Bvh::new(&self.vertices));
+		}
+		self.bvh.as_ref().unwrap()
+	}
+
+	pub fn tex_slots(&mut self) -> &[i32] {
+		if self.tex_slots.is_none() {
+			let mut slots: Vec<_> = self
+				.vertices
+				.iter()
+				.map(|x| x.tex_layer)
+				.filter(|x| *x >= 0)
+				.collect();
+			slots.sort_unstable();
+			slots.dedup();
+			self.tex_slots = Some(slots);
+		}
+		self.tex_slots.as_ref().unwrap()
+	}
+
+	pub fn mark_vertices(&mut self, range: Range<usize>) {
+		self.bvh = None;
+		self.tex_slots = None;
+		self.vertices_dirty = Some(match self.vertices_dirty.take() {
+			Some(r) => r.start.min(range.start)..r.end.max(range.end),
+			None => range,
+		});
+	}
+
+	pub fn model_data(&self) -> ModelData {
+		ModelData {
+			transform: self.transform.into(),
+			tint: self.tint,
+			opacity: self.opacity,
+			uv_u: self.uv_transform[0],
+			id: self.id,
+			uv_v: self.uv_transform[1],
+			morph_offset: self.morph_offset,
+			morph_vertices: self.morph_vertices,
+			morph_targets: self.morph_weights.len() as u32,
+			weight_offset: 0,
+			skin_offset: self.skin_offset,
+			skin_vertices: self.skin.len() as u32,
+			joint_offset: 0,
+			joint_count: self.joint_matrices.len() as u32,
+			_pad: 0,
+		}
+	}
 }
@@ -0,0 +1,78 @@
+use vulkano::pipeline::graphics::depth_stencil::{
+	CompareOp, StencilOp, StencilOpState, StencilOps, StencilState,
+};
+use vulkano::pipeline::StateMode;
+
+// usable layers 0..MASK_LAYERS, bit 7 is outline::OUTLINE_BIT
+pub const MASK_LAYERS: u8 = 7;
+
+// stencil masks, each layer is one bit of the stencil buffer
+// a writer must be drawn before models testing the same layer (use z)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MaskMode {
+	None,
+	// hidden writers affect stencil only, no color or depth
+	Write { layer: u8, hidden: bool },
+	Inside(u8),
+	Outside(u8),
+}
+
+impl Default for MaskMode {
+	fn default() -> Self {
+		Self::None
+	}
+}
+
+fn op_state(
+	compare_op: CompareOp,
+	pass_op: StencilOp,
+	bit: u32,
+	write: bool,
+) -> StencilOpState {
+	StencilOpState {
+		ops: StateMode::Fixed(StencilOps {
+			fail_op: StencilOp::Keep,
+			pass_op,
+			depth_fail_op: StencilOp::Keep,
+			compare_op,
+		}),
+		compare_mask: StateMode::Fixed(bit),
+		write_mask: StateMode::Fixed(if write { bit } else { 0 }),
+		reference: StateMode::Fixed(bit),
+	}
+}
+
+impl MaskMode {
+	pub fn layer(self) -> Option<u8> {
+		match self {
+			Self::None => None,
+			Self::Write { layer, .. } => Some(layer),
+			Self::Inside(layer) | Self::Outside(layer) => Some(layer),
+		}
+	}
+
+	pub fn hidden(self) -> bool {
+		matches!(self, Self::Write { hidden: true, .. })
+	}
+
+	pub fn stencil_state(self) -> Option<StencilState> {
+		let bit = 1u32 << self.layer()?;
+		let state = match self {
+			Self::None => return None,
+			Self::Write { .. } => {
+				op_state(CompareOp::Always, StencilOp::Replace, bit, true)
+			}
+			Self::Inside(_) => {
+				op_state(CompareOp::Equal, StencilOp::Keep, bit, false)
+			}
+			Self::Outside(_) => {
+				op_state(CompareOp::NotEqual, StencilOp::Keep, bit, false)
+			}
+		};
+		Some(StencilState {
+			enable_dynamic: false,
+			front: state,
+			back: state,
+		})
+	}
+}
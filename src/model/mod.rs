@@ -1,4 +1,9 @@
 pub use ttri_model::cmodel;
-mod compiled_model;
+pub mod blend_mode;
+mod bvh;
+pub(crate) mod compiled_model;
+pub mod mask_mode;
 pub mod model_ref;
 pub(crate) mod modelman;
+pub mod morph;
+pub mod skin;
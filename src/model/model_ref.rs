@@ -1,32 +1,263 @@
+use bytemuck::Pod;
 use std::cell::{Ref, RefCell, RefMut};
-use std::rc::Rc;
+use std::ops::Range;
+use std::rc::{Rc, Weak};
 
-use super::compiled_model::CompiledModel;
+use super::blend_mode::BlendMode;
+use super::compiled_model::{CompiledModel, Outline};
+use super::mask_mode::{MaskMode, MASK_LAYERS};
+use super::modelman::compile_faces;
+use super::morph::{self, MorphTarget};
+use super::skin::{self, Skin};
+use super::cmodel::Model;
+use crate::model_data::PushData;
+use crate::shaderman::ShaderId;
+use crate::texman::Mapper;
+use crate::vertex::VertexTex;
+use crate::M4;
+
+// evaluated per push constant type, larger types fail to build
+struct PushSize<T>(std::marker::PhantomData<T>);
+
+impl<T> PushSize<T> {
+	const OK: () = assert!(
+		std::mem::size_of::<T>() <= std::mem::size_of::<PushData>(),
+		"push constants larger than 128 bytes"
+	);
+}
 
 #[derive(Clone)]
 pub struct ModelRef {
 	data: Rc<RefCell<CompiledModel>>,
+	mapper: Mapper,
 }
 
 impl ModelRef {
-	pub fn new(compiled_model: CompiledModel) -> Self {
+	pub fn new(compiled_model: CompiledModel, mapper: Mapper) -> Self {
 		Self {
 			data: Rc::new(RefCell::new(compiled_model)),
+			mapper,
 		}
 	}
 
+	// recompile geometry, draw state is kept
+	// only this model is uploaded if the vertex count is unchanged
+	pub fn update(&mut self, model: &Model) {
+		let mut slots = self.mapper.borrow_mut();
+		let (vertices, faces, slot_refs) = compile_faces(model, &mut slots);
+		let mut data = self.data.borrow_mut();
+		// after acquiring the new ones, so shared slots stay mapped
+		slots.release(&data.slot_refs);
+		data.slot_refs = slot_refs;
+		if vertices.len() == data.vertices.len() {
+			data.mark_vertices(0..vertices.len());
+		} else {
+			data.bvh = None;
+			data.tex_slots = None;
+			data.dirty = true;
+		}
+		data.vertices = vertices;
+		data.faces = faces;
+	}
+
+	// edit compiled vertices in place, three per valid face.
+	// tex_layer may only be set to slots the model already uses
+	pub fn update_vertices(
+		&mut self,
+		range: Range<usize>,
+		f: impl FnOnce(&mut [VertexTex]),
+	) {
+		let mut data = self.data.borrow_mut();
+		f(&mut data.vertices[range.clone()]);
+		data.mark_vertices(range);
+	}
+
+	pub fn id(&self) -> u32 {
+		self.data.borrow().id
+	}
+
 	pub fn set_z(&mut self, z: i32) {
-		self.data.borrow_mut().z = z;
+		let mut data = self.data.borrow_mut();
+		data.z = z;
+		data.dirty = true;
 	}
 
 	pub fn set_visibility(&mut self, visible: bool) {
-		self.data.borrow_mut().visible = visible;
+		let mut data = self.data.borrow_mut();
+		data.visible = visible;
+		data.dirty = true;
+	}
+
+	pub fn set_blend(&mut self, blend: BlendMode) {
+		let mut data = self.data.borrow_mut();
+		data.blend = blend;
+		data.dirty = true;
+	}
+
+	pub fn set_translucent(&mut self, translucent: bool) {
+		let mut data = self.data.borrow_mut();
+		data.translucent = translucent;
+		data.dirty = true;
+	}
+
+	// out of range layers keep the old mask
+	pub fn set_mask(&mut self, mask: MaskMode) {
+		if let Some(layer) = mask.layer() {
+			if layer >= MASK_LAYERS {
+				eprintln!("ERROR: mask layer {} out of range", layer);
+				return;
+			}
+		}
+		let mut data = self.data.borrow_mut();
+		data.mask = mask;
+		data.dirty = true;
+	}
+
+	pub fn set_outline(&mut self, color: [f32; 4], width: f32) {
+		let mut data = self.data.borrow_mut();
+		data.outline = Some(Outline { color, width });
+		data.dirty = true;
+	}
+
+	pub fn remove_outline(&mut self) {
+		let mut data = self.data.borrow_mut();
+		data.outline = None;
+		data.dirty = true;
+	}
+
+	pub fn set_shader(&mut self, shader: ShaderId) {
+		let mut data = self.data.borrow_mut();
+		data.shader = shader;
+		data.dirty = true;
+	}
+
+	// model to world, applied on gpu
+	pub fn set_transform(&mut self, transform: M4) {
+		let mut data = self.data.borrow_mut();
+		data.transform = transform;
+		data.data_dirty = true;
+	}
+
+	// srgb, multiplied with the final color, textures included
+	pub fn set_tint(&mut self, tint: [f32; 3]) {
+		let mut data = self.data.borrow_mut();
+		data.tint = tint;
+		data.data_dirty = true;
+	}
+
+	// multiplied with the final alpha, no effect with BlendMode::Opaque
+	pub fn set_opacity(&mut self, opacity: f32) {
+		let mut data = self.data.borrow_mut();
+		data.opacity = opacity;
+		data.data_dirty = true;
+	}
+
+	// rows of a 2x3 affine matrix, uv' = m * (u, v, 1).
+	// sprite faces already carry page uvs, so this moves them
+	// across the page rather than within the sprite
+	pub fn set_uv_transform(&mut self, transform: [[f32; 3]; 2]) {
+		let mut data = self.data.borrow_mut();
+		data.uv_transform = transform;
+		data.data_dirty = true;
+	}
+
+	// map [0, 1] uv to [x, x + w] * [y, y + h], e.g. an atlas cell
+	pub fn set_uv_rect(&mut self, [x, y, w, h]: [f32; 4]) {
+		self.set_uv_transform([[w, 0.0, x], [0.0, h, y]]);
+	}
+
+	// blended in the vertex shader, weights are reset to zero
+	pub fn set_morph_targets(&mut self, targets: &[MorphTarget]) {
+		let (deltas, vertices) = morph::expand(targets);
+		let mut data = self.data.borrow_mut();
+		data.morph = deltas;
+		data.morph_vertices = vertices;
+		data.morph_weights = vec![0.0; targets.len()];
+		data.deform_dirty = true;
+	}
+
+	// one weight per target, read every frame without upload of deltas.
+	// extra weights are dropped
+	pub fn set_morph_weights(&mut self, weights: &[f32]) {
+		let mut data = self.data.borrow_mut();
+		let len = data.morph_weights.len();
+		if weights.len() > len {
+			eprintln!(
+				"ERROR: {} morph weights for {} targets",
+				weights.len(),
+				len,
+			);
+		}
+		let len = len.min(weights.len());
+		data.morph_weights[..len].copy_from_slice(&weights[..len]);
+	}
+
+	// linear blend skinning in the vertex shader, starts in bind pose.
+	// a skin with bad joint indices is skipped
+	pub fn set_skin(&mut self, skin: &Skin) {
+		let vertices = match skin::expand(skin) {
+			Some(x) => x,
+			None => {
+				eprintln!("ERROR: skin has joint index out of range");
+				return;
+			}
+		};
+		let mut data = self.data.borrow_mut();
+		data.skin = vertices;
+		data.inverse_bind = skin.inverse_bind.clone();
+		data.joint_matrices = vec![M4::identity(); skin.inverse_bind.len()];
+		data.deform_dirty = true;
+	}
+
+	pub fn remove_skin(&mut self) {
+		let mut data = self.data.borrow_mut();
+		data.skin.clear();
+		data.inverse_bind.clear();
+		data.joint_matrices.clear();
+		data.deform_dirty = true;
+	}
+
+	// model space joint transforms, one per joint of the skin,
+	// the pose is kept on a count mismatch
+	pub fn set_pose(&mut self, joints: &[M4]) {
+		let mut data = self.data.borrow_mut();
+		if joints.len() != data.inverse_bind.len() {
+			eprintln!(
+				"ERROR: pose has {} joints, skin has {}",
+				joints.len(),
+				data.inverse_bind.len(),
+			);
+			return;
+		}
+		let matrices = joints
+			.iter()
+			.zip(data.inverse_bind.iter())
+			.map(|(joint, inverse_bind)| joint * inverse_bind)
+			.collect();
+		data.joint_matrices = matrices;
+	}
+
+	// at most 128 bytes, checked at compile time,
+	// read by custom shaders from offset 0
+	pub fn set_push_constants<T: Pod>(&mut self, value: &T) {
+		let () = PushSize::<T>::OK;
+		let bytes = bytemuck::bytes_of(value);
+		let mut push = PushData::default();
+		let dst = bytemuck::bytes_of_mut(&mut push);
+		dst[..bytes.len()].copy_from_slice(bytes);
+		let mut data = self.data.borrow_mut();
+		data.push = push;
+		data.dirty = true;
 	}
 
 	pub fn dropped(&self) -> bool {
 		Rc::strong_count(&self.data) <= 1
 	}
 
+	pub(crate) fn downgrade(&self) -> Weak<RefCell<CompiledModel>> {
+		Rc::downgrade(&self.data)
+	}
+
 	pub fn borrow(&self) -> Ref<CompiledModel> {
 		self.data.borrow()
 	}
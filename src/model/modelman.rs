@@ -1,45 +1,75 @@
+use bytemuck::Pod;
 use std::cell::Ref;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::sync::Arc;
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
 
+use super::blend_mode::BlendMode;
 use super::cmodel::{Face, Model};
-use super::compiled_model::CompiledModel;
+use super::compiled_model::{CompiledModel, Outline};
+use super::mask_mode::MaskMode;
 use super::model_ref::ModelRef;
+use super::morph::MorphDelta;
+use super::skin::SkinVertex;
 use crate::helper::*;
+use crate::model_data::{ModelData, PushData};
+use crate::shaderman::ShaderId;
+use crate::texman::{Mapper, Slots};
 use crate::vertex::VertexTex;
+use crate::{M4, V4};
 
 const BUFSIZE: usize = 1 << 24;
 type VertexTexBuffer = Arc<CpuAccessibleBuffer<[VertexTex; BUFSIZE]>>;
+type StorageBuffer<T> = Arc<CpuAccessibleBuffer<[T]>>;
+
+// one draw per visible model, in buffer order
+#[derive(Clone, Copy, Debug)]
+pub struct DrawCall {
+	pub first: u32,
+	pub count: u32,
+	pub blend: BlendMode,
+	pub translucent: bool,
+	pub mask: MaskMode,
+	pub outline: Option<Outline>,
+	pub data: ModelData,
+	pub shader: ShaderId,
+	pub push: PushData,
+}
 
 pub struct Modelman {
 	pub buffer: VertexTexBuffer,
-	cached_size: Option<usize>, // none = dirty
+	// deform data of all models, rebuilt when any of them changes
+	pub morph_buffer: StorageBuffer<MorphDelta>,
+	pub skin_buffer: StorageBuffer<SkinVertex>,
+	memalloc: VkwMemAlloc,
+	cached_draws: Option<Vec<DrawCall>>, // none = dirty
+	// translucent part of cached_draws is sorted for this camera
+	cached_viewproj: Option<M4>,
 	models: Vec<ModelRef>,
+	id_alloc: u32,
+	mapper: Mapper,
 }
 
 fn build_face(
 	model: &Model,
 	face: &Face,
-	mapper: &HashMap<i32, i32>,
+	slots: &mut Slots,
 ) -> Option<[VertexTex; 3]> {
 	let mut vs: [VertexTex; 3] = unsafe {
 		std::mem::MaybeUninit::zeroed().assume_init()
 	};
+	// sprites sample their packed region of an atlas page
+	let (layer, rect) = match slots.sprite(face.layer) {
+		Some(sprite) => sprite,
+		None => (face.layer, [0.0, 0.0, 1.0, 1.0]),
+	};
 	for idx in 0..3 {
 		let tex_coord = if face.layer < 0 {
 			[0.0; 2]
 		} else {
 			match model.uvs.get(face.uvid[idx]) {
-				Some(x) => *x,
-				None => return None,
-			}
-		};
-		let tex_layer = if face.layer < 0 {
-			face.layer
-		} else {
-			match mapper.get(&face.layer) {
-				Some(x) => *x,
+				Some(x) => [rect[0] + x[0] * rect[2], rect[1] + x[1] * rect[3]],
 				None => return None,
 			}
 		};
@@ -51,14 +81,54 @@ fn build_face(
 			pos,
 			color: face.color,
 			tex_coord,
-			tex_layer,
+			tex_layer: face.layer,
+			vid: face.vid[idx] as u32,
 		};
 	}
+	// only valid faces take a slot
+	if face.layer >= 0 {
+		let slot = slots.alloc(layer)?;
+		for v in vs.iter_mut() {
+			v.tex_layer = slot;
+		}
+	}
 	Some(vs)
 }
 
+// compiled vertices, source face index of each triangle
+// and the texture slots acquired for them
+pub fn compile_faces(
+	model: &Model,
+	slots: &mut Slots,
+) -> (Vec<VertexTex>, Vec<usize>, Vec<i32>) {
+	let mut invalid = 0;
+	let mut vertices = Vec::new();
+	let mut faces = Vec::new();
+	for (idx, face) in model.faces.iter().enumerate() {
+		match build_face(model, face, slots) {
+			Some(vs) => {
+				vertices.extend(vs);
+				faces.push(idx);
+			}
+			None => invalid += 1,
+		}
+	}
+	if invalid > 0 {
+		eprintln!("ERROR: skipped {} invalid faces", invalid);
+	}
+	let mut used: Vec<_> = vertices
+		.iter()
+		.map(|x| x.tex_layer)
+		.filter(|x| *x >= 0)
+		.collect();
+	used.sort_unstable();
+	used.dedup();
+	slots.acquire(&used);
+	(vertices, faces, used)
+}
+
 impl Modelman {
-	pub fn new(memalloc: VkwMemAlloc) -> Self {
+	pub fn new(memalloc: VkwMemAlloc, mapper: Mapper) -> Self {
 		let buffer = unsafe {
 			CpuAccessibleBuffer::uninitialized(
 				&memalloc,
@@ -72,49 +142,70 @@ impl Modelman {
 		};
 		Self {
 			buffer,
-			cached_size: None,
+			morph_buffer: storage_buffer(&memalloc, Vec::new()),
+			skin_buffer: storage_buffer(&memalloc, Vec::new()),
+			memalloc,
+			cached_draws: None,
+			cached_viewproj: None,
 			models: Default::default(),
+			id_alloc: 1,
+			mapper,
 		}
 	}
 
-	pub fn insert(
-		&mut self,
-		model: &Model,
-		mapper: &HashMap<i32, i32>,
-	) -> ModelRef {
-		let mut invalid = 0;
-		let mut vertices = Vec::new();
-		for face in model.faces.iter() {
-			match build_face(model, face, mapper) {
-				Some(vs) => vertices.extend(vs),
-				None => invalid += 1,
-			}
-		}
-		if invalid > 0 {
-			eprintln!("ERROR: skipped {} invalid faces", invalid);
-		}
+	pub fn insert(&mut self, model: &Model) -> ModelRef {
+		let (vertices, faces, slot_refs) =
+			compile_faces(model, &mut self.mapper.borrow_mut());
 		let model = CompiledModel {
+			id: self.id_alloc,
 			visible: true,
 			z: 0,
+			blend: Default::default(),
+			translucent: false,
+			mask: Default::default(),
+			outline: None,
+			shader: Default::default(),
+			push: Default::default(),
+			transform: M4::identity(),
+			tint: [1.0; 3],
+			opacity: 1.0,
+			uv_transform: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+			morph: Vec::new(),
+			morph_vertices: 0,
+			morph_weights: Vec::new(),
+			skin: Vec::new(),
+			inverse_bind: Vec::new(),
+			joint_matrices: Vec::new(),
+			morph_offset: 0,
+			skin_offset: 0,
+			deform_dirty: false,
 			vertices,
+			faces,
+			bvh: None,
+			tex_slots: None,
+			slot_refs,
+			dirty: false,
+			data_dirty: false,
+			vertices_dirty: None,
 		};
-		let model = ModelRef::new(model);
+		self.id_alloc += 1;
+		let model = ModelRef::new(model, self.mapper.clone());
 		self.models.push(model.clone());
-		self.cached_size = None;
+		self.cached_draws = None;
 		model
 	}
 
-	pub fn map_tex(&mut self, mapper: HashMap<i32, i32>) {
-		for model in self.models.iter_mut() {
-			let mut model = model.borrow_mut();
-			for v in model.vertices.iter_mut() {
-				let l = &mut v.tex_layer;
-				if *l >= 0 {
-					*l = *mapper.get(l).unwrap();
-				}
-			}
-		}
-		self.cached_size = None;
+	pub fn visible_models(&self) -> impl Iterator<Item = &ModelRef> {
+		self.models
+			.iter()
+			.filter(|x| !x.dropped() && x.borrow().visible)
+	}
+
+	pub fn find(&self, id: u32) -> Option<ModelRef> {
+		self.models
+			.iter()
+			.find(|x| !x.dropped() && x.id() == id)
+			.cloned()
 	}
 
 	pub fn gc(&mut self) {
@@ -122,24 +213,161 @@ impl Modelman {
 			if !model.dropped() {
 				self.models.push(model);
 			} else {
-				self.cached_size = None;
+				let data = model.borrow();
+				self.mapper.borrow_mut().release(&data.slot_refs);
+				drop(data);
+				self.cached_draws = None;
 			}
 		}
 	}
 
-	pub fn write_buffer(&mut self) -> Option<usize> {
+	pub fn invalidate(&mut self) {
+		self.cached_draws = None;
+	}
+
+	// offsets change, so cached draws are invalidated
+	fn write_deform(&mut self) {
+		let mut deltas = Vec::new();
+		let mut skins = Vec::new();
+		for model in self.models.iter() {
+			let mut model = model.borrow_mut();
+			model.deform_dirty = false;
+			model.morph_offset = deltas.len() as u32;
+			deltas.extend_from_slice(&model.morph);
+			model.skin_offset = skins.len() as u32;
+			skins.extend_from_slice(&model.skin);
+		}
+		self.morph_buffer = storage_buffer(&self.memalloc, deltas);
+		self.skin_buffer = storage_buffer(&self.memalloc, skins);
+		self.cached_draws = None;
+	}
+
+	// current morph weights and joint matrices of deformed draws,
+	// weight_offset and joint_offset are patched
+	pub fn deform_data(
+		&self,
+		draws: &mut [DrawCall],
+	) -> (Vec<f32>, Vec<[[f32; 4]; 4]>) {
+		let mut weights = Vec::new();
+		let mut joints = Vec::new();
+		let deformed = |x: &DrawCall| {
+			x.data.morph_targets > 0 || x.data.joint_count > 0
+		};
+		if !draws.iter().any(deformed) {
+			return (weights, joints);
+		}
+		// draws only carry ids, index once instead of a search per draw
+		let models: HashMap<u32, &ModelRef> =
+			self.models.iter().map(|x| (x.id(), x)).collect();
+		for draw in draws.iter_mut().filter(|x| deformed(x)) {
+			let model = match models.get(&draw.data.id) {
+				Some(model) => model.borrow(),
+				None => continue,
+			};
+			draw.data.weight_offset = weights.len() as u32;
+			weights.extend_from_slice(&model.morph_weights);
+			draw.data.joint_offset = joints.len() as u32;
+			joints.extend(model.joint_matrices.iter().map(|x| (*x).into()));
+		}
+		(weights, joints)
+	}
+
+	// rewrite changed vertex ranges of opaque models in place
+	fn write_ranges(&self, updates: &[(ModelRef, Range<usize>)]) -> bool {
+		let mut writer = if let Ok(writer) = self.buffer.write() {
+			writer
+		} else {
+			eprintln!("ERROR: Gpu locked");
+			return false;
+		};
+		let draws = self.cached_draws.as_ref().unwrap();
+		for (model, range) in updates.iter() {
+			let model = model.borrow();
+			// invisible models have no draw
+			let draw = match draws.iter().find(|x| x.data.id == model.id) {
+				Some(draw) => draw,
+				None => continue,
+			};
+			let offset = draw.first as usize;
+			writer[offset + range.start..offset + range.end]
+				.copy_from_slice(&model.vertices[range.clone()]);
+		}
+		true
+	}
+
+	// viewproj is none if translucent faces need no sorting
+	pub fn write_buffer(
+		&mut self,
+		viewproj: Option<M4>,
+	) -> Option<Vec<DrawCall>> {
 		self.gc();
-		if self.cached_size.is_some() {
-			return self.cached_size;
+		if self.models.iter().any(|x| x.borrow().deform_dirty) {
+			self.write_deform();
 		}
-		let mut buffers: Vec<Ref<CompiledModel>> = self
+		let mut data_updates = HashMap::new();
+		let mut vertex_updates = Vec::new();
+		let mut translucent_updated = false;
+		for model_ref in self.models.iter() {
+			let mut model = model_ref.borrow_mut();
+			if model.dirty {
+				model.dirty = false;
+				self.cached_draws = None;
+			}
+			if model.data_dirty {
+				model.data_dirty = false;
+				data_updates.insert(model.id, model.model_data());
+				// transform may change the sort order
+				translucent_updated |= model.translucent;
+			}
+			if let Some(range) = model.vertices_dirty.take() {
+				if model.translucent {
+					translucent_updated = true;
+				} else {
+					vertex_updates.push((model_ref.clone(), range));
+				}
+			}
+		}
+		// patch cached draws in place, vertices stay untouched
+		if let Some(draws) = self.cached_draws.as_mut() {
+			for draw in draws.iter_mut() {
+				if let Some(data) = data_updates.get(&draw.data.id) {
+					draw.data = *data;
+				}
+			}
+		}
+		let opaque_dirty = self.cached_draws.is_none();
+		if !opaque_dirty && !vertex_updates.is_empty() {
+			if !self.write_ranges(&vertex_updates) {
+				self.cached_draws = None;
+				return None;
+			}
+		}
+		let translucent_dirty = opaque_dirty
+			|| translucent_updated
+			|| (viewproj.is_some() && self.cached_viewproj != viewproj);
+		if !translucent_dirty {
+			return self.cached_draws.clone();
+		}
+
+		let (mut opaque, mut translucent): (Vec<_>, Vec<_>) = self
 			.models
 			.iter()
 			.map(|x| x.borrow())
 			.filter(|x| x.visible)
-			.collect();
-		buffers.sort_by_key(|x| x.z);
-		let len = buffers.iter().map(|x| x.vertices.len()).sum();
+			.partition(|x| !x.translucent);
+		if !opaque_dirty && translucent.is_empty() {
+			self.cached_viewproj = viewproj;
+			return self.cached_draws.clone();
+		}
+		let len: usize = opaque
+			.iter()
+			.chain(translucent.iter())
+			.map(|x| x.vertices.len())
+			.sum();
+		if len > BUFSIZE {
+			eprintln!("ERROR: vertex buffer overflow");
+			return None;
+		}
 
 		let buffer = self.buffer.clone();
 		let mut writer = if let Ok(writer) = buffer.write() {
@@ -148,13 +376,124 @@ impl Modelman {
 			eprintln!("ERROR: Gpu locked");
 			return None;
 		};
-		for (v, w) in writer
-			.iter_mut()
-			.zip(buffers.iter().flat_map(|x| &x.vertices))
-		{
-			*v = *w;
+
+		// opaque models keep z order and are only rewritten when changed
+		let mut draws = Vec::new();
+		let mut first = 0;
+		opaque.sort_by_key(|x| x.z);
+		for model in opaque.iter() {
+			let count = model.vertices.len() as u32;
+			if opaque_dirty {
+				let range = first as usize..(first + count) as usize;
+				writer[range].copy_from_slice(&model.vertices);
+			}
+			if count > 0 {
+				draws.push(DrawCall {
+					first,
+					count,
+					blend: model.blend,
+					translucent: false,
+					mask: model.mask,
+					outline: model.outline,
+					data: model.model_data(),
+					shader: model.shader,
+					push: model.push,
+				});
+			}
+			first += count;
+		}
+
+		// translucent models and their faces are drawn back to front
+		translucent.sort_by_key(|x| x.z);
+		let mut translucent: Vec<(f32, Ref<CompiledModel>)> = translucent
+			.drain(..)
+			.map(|x| match viewproj.as_ref() {
+				Some(vp) => {
+					let mvp = vp * x.transform;
+					(model_depth(&mvp, &x.vertices), x)
+				}
+				None => (0.0, x),
+			})
+			.collect();
+		translucent.sort_by(|a, b| b.0.total_cmp(&a.0));
+		for (_, model) in translucent.iter() {
+			let count = model.vertices.len() as u32;
+			let mvp = viewproj.map(|vp| vp * model.transform);
+			let mut faces: Vec<(f32, &[VertexTex])> = model
+				.vertices
+				.chunks_exact(3)
+				.map(|face| match mvp.as_ref() {
+					Some(mvp) => (model_depth(mvp, face), face),
+					None => (0.0, face),
+				})
+				.collect();
+			if viewproj.is_some() {
+				faces.sort_by(|a, b| b.0.total_cmp(&a.0));
+			}
+			for (idx, (_, face)) in faces.into_iter().enumerate() {
+				let offset = first as usize + idx * 3;
+				writer[offset..offset + 3].copy_from_slice(face);
+			}
+			if count > 0 {
+				draws.push(DrawCall {
+					first,
+					count,
+					blend: model.blend,
+					translucent: true,
+					mask: model.mask,
+					outline: model.outline,
+					data: model.model_data(),
+					shader: model.shader,
+					push: model.push,
+				});
+			}
+			first += count;
+		}
+
+		self.cached_draws = Some(draws.clone());
+		self.cached_viewproj = viewproj;
+		Some(draws)
+	}
+}
+
+// storage buffer can not be empty
+pub fn storage_buffer<T>(
+	memalloc: &VkwMemAlloc,
+	mut data: Vec<T>,
+) -> StorageBuffer<T>
+where
+	T: Pod + Default + Send + Sync,
+{
+	if data.is_empty() {
+		data.push(Default::default());
+	}
+	CpuAccessibleBuffer::from_iter(
+		memalloc,
+		BufferUsage {
+			storage_buffer: true,
+			..BufferUsage::empty()
+		},
+		false,
+		data,
+	)
+	.unwrap()
+}
+
+// mean normalized device depth, larger is farther
+// vertices behind the camera have no meaningful depth and are skipped,
+// models entirely behind it sort to the back
+fn model_depth(viewproj: &M4, vertices: &[VertexTex]) -> f32 {
+	let mut sum = 0.0;
+	let mut count = 0;
+	for v in vertices.iter() {
+		let clip = viewproj * V4::from(v.pos);
+		if clip.w > f32::EPSILON {
+			sum += clip.z / clip.w;
+			count += 1;
 		}
-		self.cached_size = Some(len);
-		Some(len)
 	}
+	if count == 0 {
+		return f32::INFINITY;
+	}
+	sum / count as f32
 }
@@ -0,0 +1,40 @@
+use bytemuck::{Pod, Zeroable};
+
+// deltas are indexed like cmodel::Model::vs, missing entries are zero,
+// colors are srgb like face colors
+#[derive(Clone, Debug, Default)]
+pub struct MorphTarget {
+	pub positions: Vec<[f32; 3]>,
+	pub colors: Vec<[f32; 4]>,
+}
+
+// matches vert.glsl
+#[repr(C)]
+#[derive(Pod, Zeroable, Default, Debug, Clone, Copy)]
+pub struct MorphDelta {
+	pub pos: [f32; 4],
+	pub color: [f32; 4],
+}
+
+// target major, vertices deltas per target
+pub fn expand(targets: &[MorphTarget]) -> (Vec<MorphDelta>, u32) {
+	let vertices = targets
+		.iter()
+		.map(|x| x.positions.len().max(x.colors.len()))
+		.max()
+		.unwrap_or(0);
+	let mut deltas = vec![MorphDelta::default(); vertices * targets.len()];
+	if vertices == 0 {
+		return (deltas, 0);
+	}
+	let chunks = deltas.chunks_exact_mut(vertices);
+	for (target, chunk) in targets.iter().zip(chunks) {
+		for (delta, pos) in chunk.iter_mut().zip(target.positions.iter()) {
+			delta.pos = [pos[0], pos[1], pos[2], 0.0];
+		}
+		for (delta, color) in chunk.iter_mut().zip(target.colors.iter()) {
+			delta.color = *color;
+		}
+	}
+	(deltas, vertices as u32)
+}
@@ -0,0 +1,40 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::M4;
+
+// per vertex data is indexed like cmodel::Model::vs,
+// vertices without skin data are not deformed
+#[derive(Clone, Debug, Default)]
+pub struct Skin {
+	// up to four joints per vertex, unused weights are zero
+	pub joints: Vec<[u32; 4]>,
+	pub weights: Vec<[f32; 4]>,
+	// model space to joint space in bind pose, one per joint
+	pub inverse_bind: Vec<M4>,
+}
+
+// matches vert.glsl
+#[repr(C)]
+#[derive(Pod, Zeroable, Default, Debug, Clone, Copy)]
+pub struct SkinVertex {
+	pub joints: [u32; 4],
+	pub weights: [f32; 4],
+}
+
+// none if a joint index is out of range, even with zero weight
+pub fn expand(skin: &Skin) -> Option<Vec<SkinVertex>> {
+	let joint_len = skin.inverse_bind.len() as u32;
+	skin.joints
+		.iter()
+		.zip(skin.weights.iter())
+		.map(|(joints, weights)| {
+			if !joints.iter().all(|x| *x < joint_len) {
+				return None;
+			}
+			Some(SkinVertex {
+				joints: *joints,
+				weights: *weights,
+			})
+		})
+		.collect()
+}
@@ -0,0 +1,64 @@
+use bytemuck::{Pod, Zeroable};
+
+// per draw data, indexed by gl_InstanceIndex in shaders
+// std430 layout, see vert.glsl
+#[repr(C)]
+#[derive(Pod, Zeroable, Debug, Clone, Copy, PartialEq)]
+pub struct ModelData {
+	// model to world, column major
+	pub transform: [[f32; 4]; 4],
+	// srgb like face colors, multiplied with them
+	pub tint: [f32; 3],
+	pub opacity: f32,
+	// rows of the 2x3 affine uv transform
+	pub uv_u: [f32; 3],
+	// 0 is reserved for background in the picking attachment
+	pub id: u32,
+	pub uv_v: [f32; 3],
+	// first delta in morph buffer
+	pub morph_offset: u32,
+	// deltas per target
+	pub morph_vertices: u32,
+	pub morph_targets: u32,
+	// first weight in weight buffer
+	pub weight_offset: u32,
+	// first vertex in skin buffer
+	pub skin_offset: u32,
+	pub skin_vertices: u32,
+	// first matrix in joint buffer
+	pub joint_offset: u32,
+	pub joint_count: u32,
+	pub _pad: u32,
+}
+
+impl Default for ModelData {
+	fn default() -> Self {
+		Self {
+			transform: [
+				[1.0, 0.0, 0.0, 0.0],
+				[0.0, 1.0, 0.0, 0.0],
+				[0.0, 0.0, 1.0, 0.0],
+				[0.0, 0.0, 0.0, 1.0],
+			],
+			tint: [1.0; 3],
+			opacity: 1.0,
+			uv_u: [1.0, 0.0, 0.0],
+			id: 0,
+			uv_v: [0.0, 1.0, 0.0],
+			morph_offset: 0,
+			morph_vertices: 0,
+			morph_targets: 0,
+			weight_offset: 0,
+			skin_offset: 0,
+			skin_vertices: 0,
+			joint_offset: 0,
+			joint_count: 0,
+			_pad: 0,
+		}
+	}
+}
+
+// user push constant block of custom shaders
+#[repr(C)]
+#[derive(Pod, Zeroable, Default, Debug, Clone, Copy, PartialEq)]
+pub struct PushData(pub [u32; 32]);
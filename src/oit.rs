@@ -0,0 +1,145 @@
+// weighted blended order-independent transparency
+// translucent faces accumulate into accum/reveal in subpass 1,
+// then get composited onto color in subpass 2
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::pipeline::graphics::color_blend::{
+	AttachmentBlend, BlendFactor, BlendOp, ColorBlendState,
+};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+use vulkano::shader::ShaderModule;
+
+use crate::base::{srgb_format, Base};
+use crate::helper::*;
+use crate::model::blend_mode::BlendMode;
+use crate::rmod::FragSpec;
+
+pub const SUBPASS_ACCUM: u32 = 1;
+pub const SUBPASS_COMPOSITE: u32 = 2;
+
+pub struct Oit {
+	base: Base,
+	pipeline: VkwPipeline,
+	set: VkwDescSet,
+}
+
+pub fn load_shader_oit(device: VkwDevice) -> VkwShader {
+	unsafe {
+		let fs = include_bytes!("shader/oit_frag.spv");
+		ShaderModule::from_bytes(device, fs).unwrap()
+	}
+}
+
+// accum: sum of weighted premultiplied color
+// reveal: product of (1 - alpha)
+pub fn accum_blend_state() -> ColorBlendState {
+	let mut state = ColorBlendState::new(2);
+	state.attachments[0].blend = Some(AttachmentBlend {
+		color_op: BlendOp::Add,
+		color_source: BlendFactor::One,
+		color_destination: BlendFactor::One,
+		alpha_op: BlendOp::Add,
+		alpha_source: BlendFactor::One,
+		alpha_destination: BlendFactor::One,
+	});
+	state.attachments[1].blend = Some(AttachmentBlend {
+		color_op: BlendOp::Add,
+		color_source: BlendFactor::Zero,
+		color_destination: BlendFactor::OneMinusSrcColor,
+		alpha_op: BlendOp::Add,
+		alpha_source: BlendFactor::Zero,
+		alpha_destination: BlendFactor::OneMinusSrcAlpha,
+	});
+	state
+}
+
+fn get_pipeline_composite(
+	render_pass: VkwRenderPass,
+	device: VkwDevice,
+) -> VkwPipeline {
+	let (vs, fs) = unsafe {
+		let vs = include_bytes!("shader/composite_vert.spv");
+		let vs = ShaderModule::from_bytes(device.clone(), vs).unwrap();
+		let fs = include_bytes!("shader/composite_frag.spv");
+		let fs = ShaderModule::from_bytes(device.clone(), fs).unwrap();
+		(vs, fs)
+	};
+	let frag_spec = FragSpec {
+		srgb_target: render_pass.attachments()[0]
+			.format
+			.map_or(false, srgb_format) as u32,
+	};
+	let subpass = Subpass::from(render_pass, SUBPASS_COMPOSITE).unwrap();
+	let mut color_blend_state = ColorBlendState::new(1);
+	color_blend_state.attachments[0].blend =
+		BlendMode::Alpha.attachment_blend();
+	GraphicsPipeline::start()
+		.vertex_input_state(BuffersDefinition::new())
+		.vertex_shader(vs.entry_point("main").unwrap(), ())
+		.input_assembly_state(InputAssemblyState::new())
+		.viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+		.fragment_shader(fs.entry_point("main").unwrap(), frag_spec)
+		.color_blend_state(color_blend_state)
+		.render_pass(subpass)
+		.build(device)
+		.unwrap()
+}
+
+fn get_set(
+	base: &Base,
+	pipeline: &VkwPipeline,
+	accum: VkwAttachmentView,
+	reveal: VkwAttachmentView,
+) -> VkwDescSet {
+	let layout = pipeline.layout().set_layouts().get(0).unwrap();
+	PersistentDescriptorSet::new(
+		&base.dstalloc,
+		layout.clone(),
+		[
+			WriteDescriptorSet::image_view(0, accum),
+			WriteDescriptorSet::image_view(1, reveal),
+		],
+	)
+	.unwrap()
+}
+
+impl Oit {
+	pub fn new(
+		base: Base,
+		render_pass: VkwRenderPass,
+		accum: VkwAttachmentView,
+		reveal: VkwAttachmentView,
+	) -> Self {
+		let pipeline =
+			get_pipeline_composite(render_pass, base.device.clone());
+		let set = get_set(&base, &pipeline, accum, reveal);
+		Self {
+			base,
+			pipeline,
+			set,
+		}
+	}
+
+	pub fn update_views(
+		&mut self,
+		accum: VkwAttachmentView,
+		reveal: VkwAttachmentView,
+	) {
+		self.set = get_set(&self.base, &self.pipeline, accum, reveal);
+	}
+
+	// must be called in the composite subpass
+	pub fn composite(&self, builder: &mut VkwCommandBuilder) {
+		builder.bind_pipeline_graphics(self.pipeline.clone());
+		builder.bind_descriptor_sets(
+			PipelineBindPoint::Graphics,
+			self.pipeline.layout().clone(),
+			0,
+			self.set.clone(),
+		);
+		builder.draw(3, 1, 0, 0).unwrap();
+	}
+}
@@ -0,0 +1,196 @@
+// screen space selection outline
+// silhouettes of outlined models are written to stencil bit 7,
+// then each model is drawn again shifted in several directions
+// where that bit is not set
+use bytemuck::{Pod, Zeroable};
+use vulkano::pipeline::graphics::color_blend::{
+	ColorBlendState, ColorComponents,
+};
+use vulkano::pipeline::graphics::depth_stencil::{
+	CompareOp, DepthStencilState, StencilOp, StencilOpState, StencilOps,
+	StencilState,
+};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::{GraphicsPipeline, StateMode};
+use vulkano::render_pass::Subpass;
+use vulkano::shader::ShaderModule;
+
+use crate::base::srgb_format;
+use crate::helper::*;
+use crate::model::blend_mode::BlendMode;
+use crate::model::mask_mode::MASK_LAYERS;
+use crate::model::modelman::DrawCall;
+use crate::rmod::FragSpec;
+use crate::vertex::VertexTex;
+
+// above the mask layers, see mask_mode::MASK_LAYERS
+pub const OUTLINE_BIT: u32 = 1 << MASK_LAYERS;
+
+#[repr(C)]
+#[derive(Pod, Zeroable, Debug, Clone, Copy)]
+struct OutlinePush {
+	color: [f32; 4],
+	// in normalized device coordinates
+	offset: [f32; 2],
+	_pad: [f32; 2],
+}
+
+pub struct OutlinePipelines {
+	silhouette: VkwPipeline,
+	outline: VkwPipeline,
+}
+
+fn stencil_state(compare_op: CompareOp, pass_op: StencilOp) -> StencilState {
+	let state = StencilOpState {
+		ops: StateMode::Fixed(StencilOps {
+			fail_op: StencilOp::Keep,
+			pass_op,
+			depth_fail_op: StencilOp::Keep,
+			compare_op,
+		}),
+		compare_mask: StateMode::Fixed(OUTLINE_BIT),
+		write_mask: StateMode::Fixed(OUTLINE_BIT),
+		reference: StateMode::Fixed(OUTLINE_BIT),
+	};
+	StencilState {
+		enable_dynamic: false,
+		front: state,
+		back: state,
+	}
+}
+
+fn get_pipeline(
+	render_pass: VkwRenderPass,
+	device: VkwDevice,
+	layout: VkwPipelineLayout,
+	(vs, fs): &(VkwShader, VkwShader),
+	silhouette: bool,
+) -> VkwPipeline {
+	let frag_spec = FragSpec {
+		srgb_target: render_pass.attachments()[0]
+			.format
+			.map_or(false, srgb_format) as u32,
+	};
+	let subpass = Subpass::from(render_pass, 0).unwrap();
+	let mut color_blend_state =
+		ColorBlendState::new(subpass.num_color_attachments());
+	if silhouette {
+		color_blend_state.attachments[0].color_write_mask =
+			ColorComponents::empty();
+	} else {
+		color_blend_state.attachments[0].blend =
+			BlendMode::Alpha.attachment_blend();
+	}
+	// outlines are not pickable
+	color_blend_state.attachments[1].color_write_mask =
+		ColorComponents::empty();
+	let stencil = if silhouette {
+		stencil_state(CompareOp::Always, StencilOp::Replace)
+	} else {
+		stencil_state(CompareOp::NotEqual, StencilOp::Keep)
+	};
+	let depth_stencil_state = DepthStencilState {
+		stencil: Some(stencil),
+		..DepthStencilState::disabled()
+	};
+	GraphicsPipeline::start()
+		.vertex_input_state(BuffersDefinition::new().vertex::<VertexTex>())
+		.vertex_shader(vs.entry_point("main").unwrap(), ())
+		.input_assembly_state(InputAssemblyState::new())
+		.viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+		.fragment_shader(fs.entry_point("main").unwrap(), frag_spec)
+		.depth_stencil_state(depth_stencil_state)
+		.color_blend_state(color_blend_state)
+		.render_pass(subpass)
+		.with_pipeline_layout(device, layout)
+		.unwrap()
+}
+
+pub fn load_shaders_outline(device: VkwDevice) -> (VkwShader, VkwShader) {
+	unsafe {
+		let vs = include_bytes!("shader/outline_vert.spv");
+		let vs = ShaderModule::from_bytes(device.clone(), vs).unwrap();
+		let fs = include_bytes!("shader/outline_frag.spv");
+		let fs = ShaderModule::from_bytes(device, fs).unwrap();
+		(vs, fs)
+	}
+}
+
+// rings of 8 samples every 2 pixels, so wide outlines have no gaps
+fn offsets(width: f32) -> Vec<[f32; 2]> {
+	let mut result = Vec::new();
+	let rings = (width / 2.0).ceil().max(1.0) as usize;
+	for ring in 1..=rings {
+		let r = width * ring as f32 / rings as f32;
+		for idx in 0..8 {
+			let angle = idx as f32 * std::f32::consts::FRAC_PI_4;
+			result.push([r * angle.cos(), r * angle.sin()]);
+		}
+	}
+	result
+}
+
+impl OutlinePipelines {
+	pub fn new(
+		render_pass: VkwRenderPass,
+		device: VkwDevice,
+		layout: VkwPipelineLayout,
+		shaders: &(VkwShader, VkwShader),
+	) -> Self {
+		let get = |silhouette| {
+			get_pipeline(
+				render_pass.clone(),
+				device.clone(),
+				layout.clone(),
+				shaders,
+				silhouette,
+			)
+		};
+		Self {
+			silhouette: get(true),
+			outline: get(false),
+		}
+	}
+
+	// must be called in subpass 0 with vertex buffer and sets bound
+	pub fn draw(
+		&self,
+		builder: &mut VkwCommandBuilder,
+		layout: VkwPipelineLayout,
+		draws: &[DrawCall],
+		viewport_size: [f32; 2],
+	) {
+		// instance index is the slot in model data buffer
+		let draws: Vec<_> = draws
+			.iter()
+			.enumerate()
+			.filter_map(|(slot, draw)| {
+				draw.outline.map(|x| (slot as u32, draw, x))
+			})
+			.collect();
+		if draws.is_empty() {
+			return;
+		}
+		builder.bind_pipeline_graphics(self.silhouette.clone());
+		for (slot, draw, _) in draws.iter() {
+			builder.draw(draw.count, 1, draw.first, *slot).unwrap();
+		}
+		builder.bind_pipeline_graphics(self.outline.clone());
+		for (slot, draw, outline) in draws.iter() {
+			for [dx, dy] in offsets(outline.width) {
+				let push = OutlinePush {
+					color: outline.color,
+					offset: [
+						dx * 2.0 / viewport_size[0],
+						dy * 2.0 / viewport_size[1],
+					],
+					_pad: [0.0; 2],
+				};
+				builder.push_constants(layout.clone(), 0, push);
+				builder.draw(draw.count, 1, draw.first, *slot).unwrap();
+			}
+		}
+	}
+}
@@ -0,0 +1,116 @@
+// object id readback from the picking attachment
+// a requested rectangle is copied after the frame is drawn,
+// and becomes readable once the gpu released the buffer
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{BufferImageCopy, CopyImageToBufferInfo};
+use vulkano::image::{AttachmentImage, ImageAccess};
+
+use crate::helper::*;
+
+type IdBuffer = Arc<CpuAccessibleBuffer<[u32]>>;
+
+#[derive(Default)]
+pub struct Picker {
+	pub enabled: bool,
+	request: Option<[u32; 4]>,
+	pending: Option<([u32; 4], IdBuffer)>,
+	result: Option<([u32; 4], Vec<u32>)>,
+}
+
+impl Picker {
+	// rect is [x, y, w, h] in pixels
+	pub fn request(&mut self, rect: [u32; 4]) {
+		self.request = Some(rect);
+	}
+
+	// must be called outside render pass
+	pub fn record(
+		&mut self,
+		builder: &mut VkwCommandBuilder,
+		image: Arc<AttachmentImage>,
+		memalloc: &VkwMemAlloc,
+	) {
+		if !self.enabled {
+			return;
+		}
+		let mut rect = match self.request.take() {
+			Some(rect) => rect,
+			None => return,
+		};
+		let [w, h] = image.dimensions().width_height();
+		rect[0] = rect[0].min(w);
+		rect[1] = rect[1].min(h);
+		rect[2] = rect[2].min(w - rect[0]);
+		rect[3] = rect[3].min(h - rect[1]);
+		if rect[2] == 0 || rect[3] == 0 {
+			return;
+		}
+		let buffer = unsafe {
+			CpuAccessibleBuffer::uninitialized_array(
+				memalloc,
+				(rect[2] * rect[3]) as u64,
+				BufferUsage {
+					transfer_dst: true,
+					..BufferUsage::empty()
+				},
+				true,
+			)
+			.unwrap()
+		};
+		let region = BufferImageCopy {
+			image_subresource: image.subresource_layers(),
+			image_offset: [rect[0], rect[1], 0],
+			image_extent: [rect[2], rect[3], 1],
+			..Default::default()
+		};
+		builder
+			.copy_image_to_buffer(CopyImageToBufferInfo {
+				regions: [region].into(),
+				..CopyImageToBufferInfo::image_buffer(image, buffer.clone())
+			})
+			.unwrap();
+		self.pending = Some((rect, buffer));
+	}
+
+	pub fn poll(&mut self) {
+		let (rect, buffer) = match self.pending.take() {
+			Some(pending) => pending,
+			None => return,
+		};
+		let ids = match buffer.read() {
+			Ok(reader) => reader.to_vec(),
+			Err(_) => {
+				self.pending = Some((rect, buffer));
+				return;
+			}
+		};
+		self.result = Some((rect, ids));
+	}
+
+	pub fn pick(&self, pixel: [u32; 2]) -> Option<u32> {
+		let (rect, ids) = self.result.as_ref()?;
+		let x = pixel[0].checked_sub(rect[0])?;
+		let y = pixel[1].checked_sub(rect[1])?;
+		if x >= rect[2] || y >= rect[3] {
+			return None;
+		}
+		match ids[(y * rect[2] + x) as usize] {
+			0 => None,
+			id => Some(id),
+		}
+	}
+
+	// distinct ids in the last finished readback
+	pub fn ids(&self) -> Vec<u32> {
+		let ids = match self.result.as_ref() {
+			Some((_, ids)) => ids,
+			None => return Vec::new(),
+		};
+		let mut result: Vec<u32> =
+			ids.iter().copied().filter(|x| *x != 0).collect();
+		result.sort_unstable();
+		result.dedup();
+		result
+	}
+}
@@ -0,0 +1,87 @@
+// cpu picking against compiled models, no gpu readback involved
+use crate::camera::Camera;
+use crate::model::model_ref::ModelRef;
+use crate::{M4, V3, V4};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+	pub origin: V3,
+	// normalized
+	pub dir: V3,
+}
+
+pub struct RayHit {
+	pub model: ModelRef,
+	// index into cmodel::Model::faces
+	pub face: usize,
+	// weights of the three face corners
+	pub bary: [f32; 3],
+	// compiled tex coords, in atlas page space for sprites
+	pub uv: [f32; 2],
+	pub pos: V3,
+	pub distance: f32,
+}
+
+impl Ray {
+	// pixel in window coordinates, size is viewport size in pixels
+	pub fn unproject(
+		camera: &Camera,
+		pixel: [f32; 2],
+		size: [f32; 2],
+	) -> Option<Self> {
+		let viewproj = M4::from(camera.proj) * M4::from(camera.view);
+		let inv = viewproj.try_inverse()?;
+		let x = pixel[0] / size[0] * 2.0 - 1.0;
+		let y = pixel[1] / size[1] * 2.0 - 1.0;
+		let near = V3::from_homogeneous(inv * V4::new(x, y, -1.0, 1.0))?;
+		let far = V3::from_homogeneous(inv * V4::new(x, y, 1.0, 1.0))?;
+		let dir = (far - near).try_normalize(f32::EPSILON)?;
+		Some(Self { origin: near, dir })
+	}
+
+	// nearest hit among visible models, morph and skinning are ignored
+	pub fn cast<'a, I>(&self, models: I) -> Option<RayHit>
+	where
+		I: IntoIterator<Item = &'a ModelRef>,
+	{
+		let mut best: Option<RayHit> = None;
+		for model in models {
+			let mut data = model.borrow_mut();
+			// the bvh is in model space, t is the same in both spaces
+			let inv = match data.transform.try_inverse() {
+				Some(inv) => inv,
+				None => continue,
+			};
+			let origin = V3::from_homogeneous(inv * self.origin.push(1.0));
+			let origin = match origin {
+				Some(origin) => origin,
+				None => continue,
+			};
+			let dir = (inv * self.dir.push(0.0)).xyz();
+			let hit = match data.bvh().raycast(&origin, &dir) {
+				Some(hit) => hit,
+				None => continue,
+			};
+			if best.as_ref().map_or(false, |x| x.distance <= hit.t) {
+				continue;
+			}
+			let [u, v] = hit.uv;
+			let bary = [1.0 - u - v, u, v];
+			let corners = &data.vertices[hit.tri * 3..hit.tri * 3 + 3];
+			let mut uv = [0.0; 2];
+			for (corner, w) in corners.iter().zip(bary.iter()) {
+				uv[0] += corner.tex_coord[0] * w;
+				uv[1] += corner.tex_coord[1] * w;
+			}
+			best = Some(RayHit {
+				model: model.clone(),
+				face: data.faces[hit.tri],
+				bary,
+				uv,
+				pos: self.origin + self.dir * hit.t,
+				distance: hit.t,
+			});
+		}
+		best
+	}
+}
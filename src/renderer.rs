@@ -1,8 +1,14 @@
+use image::ImageError;
 use rust_stddep::nalgebra;
 use rust_stddep::winit::event_loop::EventLoopWindowTarget;
 use rust_stddep::winit::window::Window;
+use std::path::Path;
 use std::sync::Arc;
-use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
+use std::time::Instant;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+	AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo,
+};
 use vulkano::image::ImageAccess;
 use vulkano::instance::debug::{
 	DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger,
@@ -15,50 +21,287 @@ use vulkano::swapchain::{
 };
 use vulkano::sync::{self, GpuFuture};
 
+use crate::anim::{AnimId, Animator, Property, Repeat};
+use crate::atlas::Atlas;
 use crate::base::Base;
 use crate::camera::Camera;
 use crate::helper::*;
+use crate::loader::{LoadProgress, Loader, LOAD_BATCH};
 use crate::model::cmodel::Model;
 use crate::model::model_ref::ModelRef;
+use crate::raycast::{Ray, RayHit};
 use crate::rmod::Rmod;
-use crate::teximg::Teximg;
+use crate::shaderman::{ShaderError, ShaderId};
+use crate::teximg::{TexInfo, Teximg};
+use crate::texman::{self, PLACEHOLDER};
+use crate::texture_ref::TextureRef;
+use crate::watcher;
 use crate::M4;
 
+// how translucent models are composed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transparency {
+	// back to front sorting per model and face
+	Sorted,
+	// weighted blended oit, order independent but approximate,
+	// blend mode of translucent models is ignored
+	WeightedBlended,
+}
+
 pub struct Renderer {
 	base: Base,
 	rmod: Rmod,
 	viewport: Viewport,
 	dirty: bool,
+	// last rendered camera, for raycast
+	camera: Option<Camera>,
+	animator: Animator,
+	loader: Loader,
+	last_frame: Option<Instant>,
+	// seconds between the last two rendered frames
+	frame_time: f32,
 	future: Option<VkwFuture>,
+	// upload batch, submitted once per frame
+	uploads: Option<VkwCommandBuilder>,
 	_debug_callback: Option<DebugUtilsMessenger>,
 }
 
+// the open upload batch, created by the first upload of a frame
+fn upload_builder<'a>(
+	uploads: &'a mut Option<VkwCommandBuilder>,
+	base: &Base,
+) -> &'a mut VkwCommandBuilder {
+	uploads.get_or_insert_with(|| {
+		AutoCommandBufferBuilder::primary(
+			&base.comalloc,
+			base.queue.queue_family_index(),
+			CommandBufferUsage::OneTimeSubmit,
+		)
+		.unwrap()
+	})
+}
+
 // texman
 impl Renderer {
+	// queued and submitted with others once per frame,
+	// the texture is visible when its data is resident
 	pub fn upload_tex(&mut self, image: Teximg, id: i32) {
+		self.loader.cancel(id);
+		self.queue_upload(vec![image], id);
+	}
+
+	// precomputed mip chain, level n is base size >> n,
+	// malformed chains are skipped
+	pub fn upload_tex_mips(&mut self, levels: Vec<Teximg>, id: i32) {
+		if let Err(e) = texman::check_levels(&levels) {
+			eprintln!("ERROR: mip chain of texture {}: {}", id, e);
+			return;
+		}
+		self.loader.cancel(id);
+		self.queue_upload(levels, id);
+	}
+
+	// decoded on worker threads and uploaded by render,
+	// the id samples the placeholder until then
+	pub fn load_tex(&mut self, path: &Path, id: i32, color: bool) {
+		self.load_tex_with(path, id, color, |x| x);
+	}
+
+	// configure runs on the worker thread, e.g. to set sampler or srgb
+	pub fn load_tex_with(
+		&mut self,
+		path: &Path,
+		id: i32,
+		color: bool,
+		configure: impl FnOnce(Teximg) -> Teximg + Send + 'static,
+	) {
+		let path = path.to_path_buf();
+		self.loader.load(path, id, color, Box::new(configure));
+	}
+
+	pub fn load_progress(&self) -> LoadProgress {
+		self.loader.progress()
+	}
+
+	pub fn take_load_errors(&mut self) -> Vec<(i32, ImageError)> {
+		std::mem::take(&mut self.loader.errors)
+	}
+
+	// shown by textures that are reserved, loading or evicted
+	pub fn set_placeholder_tex(&mut self, image: Teximg) {
+		self.upload_tex(image, PLACEHOLDER);
+	}
+
+	fn upload_loaded(&mut self) {
+		let loaded = self.loader.poll(LOAD_BATCH);
+		if loaded.is_empty() {
+			return;
+		}
+		for (id, image) in loaded {
+			self.queue_upload(vec![image], id);
+		}
+	}
+
+	fn queue_upload(&mut self, levels: Vec<Teximg>, id: i32) {
+		let builder = upload_builder(&mut self.uploads, &self.base);
+		let memalloc = self.base.memalloc.clone();
+		self.rmod.texman.upload(levels, id, memalloc, builder);
+	}
+
+	// only for textures uploaded with Teximg::with_dynamic,
+	// queued after pending uploads of the same texture
+	pub fn update_tex_region(
+		&mut self,
+		id: i32,
+		offset: [u32; 2],
+		image: Teximg,
+	) {
+		let builder = upload_builder(&mut self.uploads, &self.base);
+		let memalloc = &self.base.memalloc;
+		self.rmod.texman.update_region(id, offset, image, memalloc, builder);
+	}
+
+	// submit the upload batch, none if nothing is queued
+	fn submit_uploads(&mut self) -> Option<VkwFence> {
+		let builder = self.uploads.take()?;
+		let command_buffer = builder.build().unwrap();
+		let future = sync::now(self.base.device.clone())
+			.then_execute(self.base.queue.clone(), command_buffer)
+			.unwrap()
+			.boxed()
+			.then_signal_fence_and_flush()
+			.unwrap();
+		let fence = Arc::new(future);
+		let batch = self.rmod.texman.take_batch();
+		self.rmod.texman.submitted(fence.clone(), batch);
+		Some(fence)
+	}
+
+	// true until every queued texture is resident,
+	// keep rendering (or call finish_uploads) to make them visible
+	pub fn uploads_pending(&self) -> bool {
+		self.uploads.is_some() || self.rmod.texman.uploads_pending()
+	}
+
+	// submit now and block until every queued texture is resident
+	pub fn finish_uploads(&mut self) {
+		self.submit_uploads();
+		self.rmod.texman.wait_resident();
+	}
+
+	// the texture is removed when every clone of the ref is dropped
+	pub fn insert_tex(&mut self, image: Teximg) -> TextureRef {
+		let tex = self.reserve_tex();
+		self.upload_tex(image, tex.id());
+		tex
+	}
+
+	// sprite ids are usable as face layer once this returns,
+	// pages and their sprites are removed when the refs are dropped.
+	// sprites resolve when a model compiles, models created earlier
+	// need `ModelRef::update` to pick them up
+	pub fn upload_atlas(&mut self, atlas: Atlas) -> Vec<TextureRef> {
+		let mut pages = Vec::new();
+		for page in atlas.pack() {
+			let tex = self.insert_tex(page.image);
+			self.rmod.texman.add_sprites(tex.id(), page.sprites);
+			pages.push(tex);
+		}
+		pages
+	}
+
+	// id for models created before the texture is uploaded,
+	// an upload after every ref is dropped is never freed
+	pub fn reserve_tex(&mut self) -> TextureRef {
+		self.rmod.texman.reserve()
+	}
+
+	pub fn remove_tex(&mut self, outer: i32) {
+		self.loader.cancel(outer);
+		self.rmod.texman.remove(outer);
+	}
+
+	pub fn tex_loaded(&self, id: i32) -> bool {
+		self.rmod.texman.info(id).is_some()
+	}
+
+	pub fn tex_info(&self, id: i32) -> Option<TexInfo> {
+		self.rmod.texman.info(id)
+	}
+
+	// sorted, internal textures excluded
+	pub fn tex_ids(&self) -> Vec<i32> {
+		self.rmod.texman.ids()
+	}
+
+	// approximate device memory of all textures in bytes
+	pub fn tex_memory(&self) -> u64 {
+		self.rmod.texman.memory()
+	}
+
+	// bytes, evictable textures least recently drawn are dropped
+	// until under budget, none to disable
+	pub fn set_tex_budget(&mut self, budget: Option<u64>) {
+		self.rmod.texman.budget = budget;
+	}
+
+	// evicted ids since the last call, re-upload them when needed
+	pub fn take_evicted_tex(&mut self) -> Vec<i32> {
+		std::mem::take(&mut self.rmod.texman.evicted)
+	}
+
+	// read back the base level, blocks until the copy is done.
+	// none for unknown ids or images the copy is not valid for
+	pub fn download_tex(&mut self, id: i32) -> Option<Teximg> {
+		let info = self.rmod.texman.info(id)?;
+		let image = self.rmod.texman.image(id)?;
+		let texel = if info.color { 4 } else { 1 };
+		let len = (info.dim[0] * info.dim[1]) as usize * texel;
+		let buffer = CpuAccessibleBuffer::from_iter(
+			&self.base.memalloc,
+			BufferUsage {
+				transfer_dst: true,
+				..BufferUsage::empty()
+			},
+			true,
+			(0..len).map(|_| 0u8),
+		)
+		.unwrap();
 		let mut builder = AutoCommandBufferBuilder::primary(
 			&self.base.comalloc,
 			self.base.queue.queue_family_index(),
 			CommandBufferUsage::OneTimeSubmit,
 		)
 		.unwrap();
-		self.rmod.texman.upload(
-			image,
-			id,
-			self.base.memalloc.clone(),
-			&mut builder,
-		);
-		let command_buffer = Box::new(builder.build().unwrap());
-		let future = sync::now(self.base.device.clone())
+		let copy = CopyImageToBufferInfo::image_buffer(image, buffer.clone());
+		if let Err(e) = builder.copy_image_to_buffer(copy) {
+			eprintln!("ERROR: download of texture {}: {}", id, e);
+			return None;
+		}
+		let command_buffer = builder.build().unwrap();
+		// after the last frame, which reads the texture
+		let previous = match self.future.take() {
+			Some(future) => future,
+			None => sync::now(self.base.device.clone()).boxed(),
+		};
+		previous
 			.then_execute(self.base.queue.clone(), command_buffer)
 			.unwrap()
 			.then_signal_fence_and_flush()
+			.unwrap()
+			.wait(None)
 			.unwrap();
-		self.future = Some(future.boxed());
-	}
-
-	pub fn remove_tex(&mut self, outer: i32) {
-		self.rmod.texman.remove(outer);
+		let data = buffer.read().unwrap().to_vec();
+		Some(Teximg {
+			color: info.color,
+			srgb: info.srgb,
+			mipmaps: info.mip_levels > 1,
+			dynamic: info.dynamic,
+			evictable: false,
+			sampler: info.sampler,
+			dim: info.dim,
+			data,
+		})
 	}
 }
 
@@ -77,10 +320,17 @@ impl Renderer {
 			rmod,
 			viewport,
 			dirty: false,
+			camera: None,
+			animator: Default::default(),
+			loader: Default::default(),
+			last_frame: None,
+			frame_time: 0.0,
 			future: None,
+			uploads: None,
 			_debug_callback: None,
 		};
-		result.upload_tex(Teximg::luma_filled([1, 1], [0; 4]), -2);
+		result.upload_tex(Teximg::luma_filled([1, 1], [0; 4]), PLACEHOLDER);
+		result.finish_uploads();
 		result
 	}
 
@@ -113,8 +363,128 @@ impl Renderer {
 		self.dirty = true;
 	}
 
+	pub fn set_transparency(&mut self, transparency: Transparency) {
+		self.rmod.set_transparency(transparency);
+	}
+
+	pub fn frame_time(&self) -> f32 {
+		self.frame_time
+	}
+
+	// advanced by frame time in render, cancelled when model is dropped
+	pub fn animate(
+		&mut self,
+		model: &ModelRef,
+		property: Property,
+		repeat: Repeat,
+	) -> AnimId {
+		self.animator.animate(model, property, repeat)
+	}
+
+	pub fn cancel_animation(&mut self, id: AnimId) {
+		self.animator.cancel(id);
+	}
+
+	// true if the app should keep requesting redraws
+	pub fn animating(&self) -> bool {
+		!self.animator.is_empty()
+	}
+
+	// the picking attachment is only written when enabled
+	pub fn set_picking(&mut self, enabled: bool) {
+		self.rmod.set_picking(enabled);
+	}
+
+	// rect is [x, y, w, h] in pixels, read back after next render
+	pub fn request_pick(&mut self, rect: [u32; 4]) {
+		self.rmod.picker.request(rect);
+	}
+
+	// model under pixel in last finished readback
+	pub fn pick(&mut self, pixel: [u32; 2]) -> Option<ModelRef> {
+		self.rmod.picker.poll();
+		let id = self.rmod.picker.pick(pixel)?;
+		self.rmod.modelman.find(id)
+	}
+
+	// all models visible in last finished readback
+	pub fn pick_rect(&mut self) -> Vec<ModelRef> {
+		self.rmod.picker.poll();
+		self.rmod
+			.picker
+			.ids()
+			.into_iter()
+			.filter_map(|id| self.rmod.modelman.find(id))
+			.collect()
+	}
+
+	// ray through pixel with the last rendered camera
+	pub fn ray(&self, pixel: [f32; 2]) -> Option<Ray> {
+		Ray::unproject(self.camera.as_ref()?, pixel, self.viewport.dimensions)
+	}
+
+	pub fn raycast(&self, pixel: [f32; 2]) -> Option<RayHit> {
+		self.ray(pixel)?.cast(self.rmod.modelman.visible_models())
+	}
+
+	// spirv of a vertex and a fragment shader, see shaderman.rs
+	// Safety: both must be valid spirv modules as defined by the vulkan
+	// spec, vulkano does not validate the instructions
+	pub unsafe fn register_shader(
+		&mut self,
+		vs: &[u8],
+		fs: &[u8],
+	) -> Result<ShaderId, ShaderError> {
+		self.rmod.shaderman.register(vs, fs)
+	}
+
+	// .spv files are loaded as is, others are compiled as glsl
+	// which needs the glsl feature, the files are watched afterwards
+	// Safety: see watch_shader
+	pub unsafe fn load_shader(
+		&mut self,
+		vs: &Path,
+		fs: &Path,
+	) -> Result<ShaderId, ShaderError> {
+		let id = self.rmod.shaderman.register(
+			&watcher::load_spirv(vs, true)?,
+			&watcher::load_spirv(fs, false)?,
+		)?;
+		self.watch_shader(id, vs, fs)?;
+		Ok(id)
+	}
+
+	// reload the shader whenever one of the files changes,
+	// ShaderId::BUILTIN can be watched with .spv files only,
+	// naga rejects the descriptor indexing of the builtin glsl
+	// Safety: as long as they are watched, .spv files must hold valid
+	// spirv as for register_shader, glsl is compiled by naga
+	pub unsafe fn watch_shader(
+		&mut self,
+		id: ShaderId,
+		vs: &Path,
+		fs: &Path,
+	) -> Result<(), ShaderError> {
+		self.rmod.watch_shader(id, vs.to_path_buf(), fs.to_path_buf())
+	}
+
+	// also called by render, true if a shader changed
+	pub fn poll_shaders(&mut self) -> bool {
+		self.rmod.reload_shaders()
+	}
+
+	// failed reloads since last call, the previous shader stays in use
+	pub fn take_shader_errors(&mut self) -> Vec<(ShaderId, ShaderError)> {
+		std::mem::take(&mut self.rmod.shader_errors)
+	}
+
+	// models using it fall back to builtin
+	pub fn remove_shader(&mut self, id: ShaderId) {
+		self.rmod.remove_shader(id);
+	}
+
 	pub fn insert_model(&mut self, model: &Model) -> ModelRef {
-		self.rmod.modelman.insert(model, &self.rmod.texman.mapper)
+		self.rmod.modelman.insert(model)
 	}
 
 	pub fn render_p(&mut self, view: M4) {
@@ -148,6 +518,14 @@ impl Renderer {
 	}
 
 	pub fn render(&mut self, camera: Camera) {
+		self.camera = Some(camera);
+		let now = Instant::now();
+		if let Some(last_frame) = self.last_frame.replace(now) {
+			self.frame_time = (now - last_frame).as_secs_f32();
+		}
+		self.animator.tick(self.frame_time);
+		self.rmod.reload_shaders();
+		self.upload_loaded();
 		if self.dirty {
 			self.create_swapchain();
 			self.dirty = false;
@@ -173,6 +551,7 @@ impl Renderer {
 		if let Some(future) = self.future.take() {
 			drop(future);
 		}
+		let uploads = self.submit_uploads();
 		self.rmod.build_command(
 			&mut builder,
 			image_num as usize,
@@ -181,7 +560,12 @@ impl Renderer {
 		);
 		let command_buffer = Box::new(builder.build().unwrap());
 
-		let future = sync::now(self.base.device.clone())
+		// frame commands run after the upload batch
+		let previous = match uploads {
+			Some(fence) => fence.boxed(),
+			None => sync::now(self.base.device.clone()).boxed(),
+		};
+		let future = previous
 			.join(acquire_future)
 			.then_execute(self.base.queue.clone(), command_buffer)
 			.unwrap()
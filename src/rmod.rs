@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
 use vulkano::command_buffer::{RenderPassBeginInfo, SubpassContents};
 use vulkano::descriptor_set::layout::{
@@ -7,8 +10,10 @@ use vulkano::descriptor_set::layout::{
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::format::Format;
 use vulkano::image::view::ImageView;
-use vulkano::image::{AttachmentImage, ImageAccess};
-use vulkano::pipeline::graphics::color_blend::ColorBlendState;
+use vulkano::image::{AttachmentImage, ImageAccess, ImageUsage};
+use vulkano::pipeline::graphics::color_blend::{
+	ColorBlendState, ColorComponents,
+};
 use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
 use vulkano::pipeline::graphics::input_assembly::{
 	InputAssemblyState, PrimitiveTopology,
@@ -16,52 +21,173 @@ use vulkano::pipeline::graphics::input_assembly::{
 use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
 use vulkano::pipeline::graphics::viewport::Viewport;
 use vulkano::pipeline::graphics::viewport::ViewportState;
-use vulkano::pipeline::layout::{PipelineLayout, PipelineLayoutCreateInfo};
+use vulkano::pipeline::layout::{
+	PipelineLayout, PipelineLayoutCreateInfo, PushConstantRange,
+};
+use vulkano::pipeline::graphics::GraphicsPipelineCreationError;
 use vulkano::pipeline::GraphicsPipeline;
-use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::pipeline::{PipelineBindPoint, StateMode};
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
-use vulkano::shader::ShaderModule;
+use vulkano::shader::{
+	ShaderModule, ShaderStages, SpecializationConstants,
+	SpecializationMapEntry,
+};
 
-use crate::base::Base;
+use crate::base::{srgb_format, Base};
 use crate::camera::Camera;
 use crate::helper::*;
-use crate::model::modelman::Modelman;
-use crate::texman::Texman;
+use crate::model::blend_mode::BlendMode;
+use crate::model::mask_mode::MaskMode;
+use crate::model::modelman::{storage_buffer, DrawCall, Modelman};
+use crate::model_data::ModelData;
+use crate::oit::{accum_blend_state, load_shader_oit, Oit, SUBPASS_ACCUM};
+use crate::outline::{load_shaders_outline, OutlinePipelines};
+use crate::picking::Picker;
+use crate::renderer::Transparency;
+use crate::shaderman::{ShaderError, ShaderId, Shaderman};
+use crate::texman::{self, Texman};
 use crate::vertex::VertexTex;
+use crate::watcher::Watcher;
+use crate::M4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+	pub blend: BlendMode,
+	pub depth_write: bool,
+	pub oit: bool,
+	pub mask: MaskMode,
+	pub picking: bool,
+	pub shader: ShaderId,
+}
+
+impl PipelineKey {
+	pub fn from_draw(
+		draw: &DrawCall,
+		transparency: Transparency,
+		picking: bool,
+	) -> Self {
+		let oit = draw.translucent
+			&& transparency == Transparency::WeightedBlended;
+		Self {
+			blend: draw.blend,
+			depth_write: !draw.translucent && !draw.mask.hidden(),
+			oit,
+			mask: draw.mask,
+			picking,
+			shader: draw.shader,
+		}
+	}
+}
+
+pub struct SizeDependent {
+	pub framebuffers: Vec<VkwFramebuffer>,
+	pub accum: VkwAttachmentView,
+	pub reveal: VkwAttachmentView,
+	pub id: Arc<AttachmentImage>,
+}
 
 pub struct Rmod {
 	base: Base,
 	framebuffers_tex: Vec<VkwFramebuffer>,
-	pipeline_tex: VkwPipeline,
+	id_image: Arc<AttachmentImage>,
 	renderpass_tex: VkwRenderPass,
+	pub shaderman: Shaderman,
+	watcher: Watcher,
+	pub shader_errors: Vec<(ShaderId, ShaderError)>,
+	// all pipelines share one layout, so sets are bound once per frame
+	layout_tex: VkwPipelineLayout,
+	pipelines_tex: HashMap<PipelineKey, VkwPipeline>,
+	shaders_outline: (VkwShader, VkwShader),
+	pipelines_outline: Option<OutlinePipelines>,
 	pub texman: Texman,
 	pub modelman: Modelman,
 	texset: Option<VkwTextureSet>,
+	oit: Oit,
+	pub transparency: Transparency,
+	pub picker: Picker,
 }
 
 impl Rmod {
 	pub fn new(base: Base) -> Self {
 		let device = base.device.clone();
-		let renderpass_tex =
-			get_render_pass_clear(device.clone(), base.swapchain.clone());
-		let pipeline_tex = get_pipeline_tex(renderpass_tex.clone(), device, 1);
-		let framebuffers_tex = window_size_dependent_setup(
+		let renderpass_tex = get_render_pass_clear(
+			device.clone(),
+			base.swapchain.clone(),
+			base.depth_format,
+		);
+		let shaderman = Shaderman::new(
+			device.clone(),
+			load_shaders_tex(device.clone()),
+			load_shader_oit(device.clone()),
+		);
+		let shaders_outline = load_shaders_outline(device.clone());
+		let texman = Texman::new(texman::capacity(&device));
+		let layout_tex =
+			get_layout_tex(device, shaderman.builtin(), texman.capacity());
+		let size_dependent = window_size_dependent_setup(
 			renderpass_tex.clone(),
 			&base.images,
 			base.memalloc.clone(),
+			base.depth_format,
+		);
+		let oit = Oit::new(
+			base.clone(),
+			renderpass_tex.clone(),
+			size_dependent.accum,
+			size_dependent.reveal,
 		);
 		let memalloc = base.memalloc.clone();
+		let modelman = Modelman::new(memalloc, texman.mapper.clone());
 		Self {
 			base,
-			framebuffers_tex,
-			pipeline_tex,
+			framebuffers_tex: size_dependent.framebuffers,
+			id_image: size_dependent.id,
 			renderpass_tex,
-			texman: Default::default(),
-			modelman: Modelman::new(memalloc),
+			shaderman,
+			watcher: Default::default(),
+			shader_errors: Vec::new(),
+			layout_tex,
+			pipelines_tex: HashMap::new(),
+			shaders_outline,
+			pipelines_outline: None,
+			texman,
+			modelman,
 			texset: None,
+			oit,
+			transparency: Transparency::Sorted,
+			picker: Default::default(),
 		}
 	}
 
+	fn get_pipeline(&mut self, key: PipelineKey) -> VkwPipeline {
+		if let Some(pipeline) = self.pipelines_tex.get(&key) {
+			return pipeline.clone();
+		}
+		let (vs, fs) = self.shaderman.get(key.shader);
+		let fs = if key.oit { &self.shaderman.oit_fs } else { fs };
+		let pipeline = match get_pipeline_tex(
+			self.renderpass_tex.clone(),
+			self.base.device.clone(),
+			self.layout_tex.clone(),
+			(vs, fs),
+			key,
+		) {
+			Ok(pipeline) => pipeline,
+			// incompatible custom shader, fall back to builtin
+			Err(e) if key.shader != ShaderId::BUILTIN => {
+				eprintln!("ERROR: shader {:?}: {}", key.shader, e);
+				let key = PipelineKey {
+					shader: ShaderId::BUILTIN,
+					..key
+				};
+				self.get_pipeline(key)
+			}
+			Err(e) => panic!("{:?}", e),
+		};
+		self.pipelines_tex.insert(key, pipeline.clone());
+		pipeline
+	}
+
 	pub fn build_command(
 		&mut self,
 		builder: &mut VkwCommandBuilder,
@@ -69,19 +195,11 @@ impl Rmod {
 		camera: Camera,
 		viewport: Viewport,
 	) {
-		if self.texman.get_dirty() {
-			let (tex_len, update_mapper) = self.texman.tex_len();
-			if tex_len == 0 {
-				return;
-			}
-			self.modelman.map_tex(update_mapper);
-			self.pipeline_tex = get_pipeline_tex(
-				self.renderpass_tex.clone(),
-				self.base.device.clone(),
-				tex_len as u32,
-			);
-			let layout =
-				self.pipeline_tex.layout().set_layouts().get(1).unwrap();
+		self.texman.commit_resident();
+		self.texman.gc();
+		self.texman.evict();
+		if self.texman.needs_rebuild() {
+			let layout = self.layout_tex.set_layouts().get(1).unwrap();
 			let texset = self.texman.compile_set(
 				self.base.device.clone(),
 				self.base.dstalloc.clone(),
@@ -89,11 +207,25 @@ impl Rmod {
 			);
 			self.texset = texset;
 		}
+		if self.texset.is_none() {
+			return;
+		}
+		// oit does not need translucent faces sorted
+		let viewproj = match self.transparency {
+			Transparency::Sorted => {
+				Some(M4::from(camera.proj) * M4::from(camera.view))
+			}
+			Transparency::WeightedBlended => None,
+		};
 		// dirty workaround for gpulock
-		let count = match self.modelman.write_buffer() {
-			Some(count) => count,
+		let mut draws = match self.modelman.write_buffer(viewproj) {
+			Some(draws) => draws,
 			None => return,
 		};
+		let (weights, joints) = self.modelman.deform_data(&mut draws);
+		for model in self.modelman.visible_models() {
+			self.texman.touch(model.borrow_mut().tex_slots());
+		}
 
 		let uniform_buffer = CpuAccessibleBuffer::from_data(
 			&self.base.memalloc,
@@ -106,16 +238,41 @@ impl Rmod {
 		)
 		.unwrap();
 
-		let layout = self.pipeline_tex.layout().set_layouts().get(0).unwrap();
+		let model_data: Vec<ModelData> =
+			draws.iter().map(|draw| draw.data).collect();
+		let model_buffer = storage_buffer(&self.base.memalloc, model_data);
+		let weight_buffer = storage_buffer(&self.base.memalloc, weights);
+		let joint_buffer = storage_buffer(&self.base.memalloc, joints);
+
+		let layout = self.layout_tex.set_layouts().get(0).unwrap();
 		let set = PersistentDescriptorSet::new(
 			&self.base.dstalloc,
 			layout.clone(),
-			[WriteDescriptorSet::buffer(0, uniform_buffer)],
+			[
+				WriteDescriptorSet::buffer(0, uniform_buffer),
+				WriteDescriptorSet::buffer(1, model_buffer),
+				WriteDescriptorSet::buffer(
+					2,
+					self.modelman.morph_buffer.clone(),
+				),
+				WriteDescriptorSet::buffer(3, weight_buffer),
+				WriteDescriptorSet::buffer(
+					4,
+					self.modelman.skin_buffer.clone(),
+				),
+				WriteDescriptorSet::buffer(5, joint_buffer),
+			],
 		)
 		.unwrap();
 
 		let texset = self.texset.clone().unwrap();
-		let clear_values = vec![Some([0.0; 4].into()), Some(1f32.into())];
+		let clear_values = vec![
+			Some([0.0; 4].into()),
+			Some((1f32, 0u32).into()),
+			Some([0.0; 4].into()),
+			Some([1.0, 0.0, 0.0, 0.0].into()),
+			Some([0u32; 4].into()),
+		];
 		builder
 			.begin_render_pass(
 				RenderPassBeginInfo {
@@ -127,36 +284,186 @@ impl Rmod {
 				SubpassContents::Inline,
 			)
 			.unwrap()
-			.set_viewport(0, [viewport]);
+			.set_viewport(0, [viewport.clone()]);
 		builder.bind_descriptor_sets(
 			PipelineBindPoint::Graphics,
-			self.pipeline_tex.layout().clone(),
+			self.layout_tex.clone(),
 			0,
 			vec![set, texset],
 		);
+		// vulkano requires the whole range to be set before drawing
+		builder.push_constants(
+			self.layout_tex.clone(),
+			0,
+			[0u32; PUSH_CONSTANT_SIZE as usize / 4],
+		);
 		let buffer = self.modelman.buffer.clone();
-		builder.bind_pipeline_graphics(self.pipeline_tex.clone());
-		builder
-			.bind_vertex_buffers(0, buffer)
-			.draw(count as u32, 1, 0, 0)
-			.unwrap();
+		builder.bind_vertex_buffers(0, buffer);
+		let picking = self.picker.enabled;
+		let (accum, main): (Vec<_>, Vec<_>) = draws
+			.iter()
+			.enumerate()
+			.map(|(slot, draw)| {
+				let key =
+					PipelineKey::from_draw(draw, self.transparency, picking);
+				(key, slot as u32, draw)
+			})
+			.partition(|(key, _, _)| key.oit);
+		self.draw_list(builder, &main);
+		if self.pipelines_outline.is_none() {
+			self.pipelines_outline = Some(OutlinePipelines::new(
+				self.renderpass_tex.clone(),
+				self.base.device.clone(),
+				self.layout_tex.clone(),
+				&self.shaders_outline,
+			));
+		}
+		self.pipelines_outline.as_ref().unwrap().draw(
+			builder,
+			self.layout_tex.clone(),
+			&draws,
+			viewport.dimensions,
+		);
+		builder.next_subpass(SubpassContents::Inline).unwrap();
+		self.draw_list(builder, &accum);
+		builder.next_subpass(SubpassContents::Inline).unwrap();
+		if !accum.is_empty() {
+			self.oit.composite(builder);
+		}
 		builder.end_render_pass().unwrap();
+		self.picker.record(
+			builder,
+			self.id_image.clone(),
+			&self.base.memalloc,
+		);
+	}
+
+	// instance index is the slot in model data buffer
+	fn draw_list(
+		&mut self,
+		builder: &mut VkwCommandBuilder,
+		draws: &[(PipelineKey, u32, &DrawCall)],
+	) {
+		let mut bound = None;
+		let mut pushed = None;
+		for (key, slot, draw) in draws.iter() {
+			if bound != Some(*key) {
+				builder.bind_pipeline_graphics(self.get_pipeline(*key));
+				bound = Some(*key);
+			}
+			if key.shader != ShaderId::BUILTIN && pushed != Some(draw.push) {
+				builder.push_constants(self.layout_tex.clone(), 0, draw.push);
+				pushed = Some(draw.push);
+			}
+			builder.draw(draw.count, 1, draw.first, *slot).unwrap();
+		}
+	}
+
+	// Safety: see Renderer::watch_shader
+	pub unsafe fn watch_shader(
+		&mut self,
+		id: ShaderId,
+		vs: PathBuf,
+		fs: PathBuf,
+	) -> Result<(), ShaderError> {
+		if !self.shaderman.contains(id) {
+			return Err(ShaderError::Unknown(id));
+		}
+		self.watcher.watch(id, vs, fs);
+		Ok(())
+	}
+
+	// returns true if any shader was replaced
+	pub fn reload_shaders(&mut self) -> bool {
+		let mut reloaded = false;
+		for (id, spirv) in self.watcher.poll() {
+			match spirv.and_then(|(vs, fs)| self.reload_shader(id, &vs, &fs)) {
+				Ok(()) => reloaded = true,
+				Err(e) => {
+					eprintln!("ERROR: reload shader {:?}: {}", id, e);
+					self.shader_errors.push((id, e));
+				}
+			}
+		}
+		reloaded
+	}
+
+	// cached pipelines are rebuilt before the swap,
+	// so a broken shader keeps the previous ones
+	fn reload_shader(
+		&mut self,
+		id: ShaderId,
+		vs: &[u8],
+		fs: &[u8],
+	) -> Result<(), ShaderError> {
+		// Safety: the files were accepted by the watch_shader contract
+		let old = unsafe { self.shaderman.replace(id, vs, fs)? };
+		let keys: Vec<PipelineKey> = self
+			.pipelines_tex
+			.keys()
+			.filter(|key| key.shader == id)
+			.copied()
+			.collect();
+		let mut pipelines = Vec::with_capacity(keys.len());
+		for key in keys {
+			let (vs, fs) = self.shaderman.get(id);
+			let fs = if key.oit { &self.shaderman.oit_fs } else { fs };
+			match get_pipeline_tex(
+				self.renderpass_tex.clone(),
+				self.base.device.clone(),
+				self.layout_tex.clone(),
+				(vs, fs),
+				key,
+			) {
+				Ok(pipeline) => pipelines.push((key, pipeline)),
+				Err(e) => {
+					if let Some(old) = old {
+						self.shaderman.restore(id, old);
+					}
+					return Err(ShaderError::Pipeline(e));
+				}
+			}
+		}
+		self.pipelines_tex.extend(pipelines);
+		Ok(())
+	}
+
+	pub fn remove_shader(&mut self, id: ShaderId) {
+		self.watcher.unwatch(id);
+		self.shaderman.remove(id);
+		self.pipelines_tex.retain(|key, _| key.shader != id);
+	}
+
+	pub fn set_picking(&mut self, enabled: bool) {
+		self.picker.enabled = enabled;
+	}
+
+	pub fn set_transparency(&mut self, transparency: Transparency) {
+		if self.transparency != transparency {
+			self.transparency = transparency;
+			self.modelman.invalidate();
+		}
 	}
 
 	pub fn update_framebuffers(&mut self, images: &VkwImages) {
-		self.framebuffers_tex = window_size_dependent_setup(
+		let size_dependent = window_size_dependent_setup(
 			self.renderpass_tex.clone(),
 			images,
 			self.base.memalloc.clone(),
+			self.base.depth_format,
 		);
+		self.framebuffers_tex = size_dependent.framebuffers;
+		self.id_image = size_dependent.id;
+		self.oit.update_views(size_dependent.accum, size_dependent.reveal);
 	}
 }
 
 pub fn get_render_pass_clear(
 	device: VkwDevice,
 	swapchain: VkwSwapchain,
+	depth_format: Format,
 ) -> VkwRenderPass {
-	vulkano::single_pass_renderpass!(
+	vulkano::ordered_passes_renderpass!(
 		device,
 		attachments: {
 			color: {
@@ -168,30 +475,85 @@ pub fn get_render_pass_clear(
 			depth: {
 				load: Clear,
 				store: Store,
-				format: Format::D16_UNORM,
+				format: depth_format,
+				samples: 1,
+			},
+			accum: {
+				load: Clear,
+				store: DontCare,
+				format: Format::R16G16B16A16_SFLOAT,
+				samples: 1,
+			},
+			reveal: {
+				load: Clear,
+				store: DontCare,
+				format: Format::R16_SFLOAT,
+				samples: 1,
+			},
+			id: {
+				load: Clear,
+				store: Store,
+				format: Format::R32_UINT,
 				samples: 1,
 			}
 		},
-		pass: {
-			color: [color],
-			depth_stencil: {depth}
-		}
+		passes: [
+			{
+				color: [color, id],
+				depth_stencil: {depth},
+				input: []
+			},
+			{
+				color: [accum, reveal],
+				depth_stencil: {depth},
+				input: []
+			},
+			{
+				color: [color],
+				depth_stencil: {},
+				input: [accum, reveal]
+			}
+		]
 	)
 	.unwrap()
 }
 
-pub fn get_pipeline_tex(
-	render_pass: VkwRenderPass,
-	device: VkwDevice,
-	tex_len: u32,
-) -> VkwPipeline {
-	let (vs, fs) = unsafe {
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FragSpec {
+	pub srgb_target: u32,
+}
+
+unsafe impl SpecializationConstants for FragSpec {
+	fn descriptors() -> &'static [SpecializationMapEntry] {
+		static DESCRIPTORS: [SpecializationMapEntry; 1] =
+			[SpecializationMapEntry {
+				constant_id: 0,
+				offset: 0,
+				size: 4,
+			}];
+		&DESCRIPTORS
+	}
+}
+
+// the minimum guaranteed by vulkan, shared by every pipeline
+pub const PUSH_CONSTANT_SIZE: u32 = 128;
+
+pub fn load_shaders_tex(device: VkwDevice) -> (VkwShader, VkwShader) {
+	unsafe {
 		let vs = include_bytes!("shader/vert.spv");
 		let vs = ShaderModule::from_bytes(device.clone(), vs).unwrap();
 		let fs = include_bytes!("shader/frag.spv");
-		let fs = ShaderModule::from_bytes(device.clone(), fs).unwrap();
+		let fs = ShaderModule::from_bytes(device, fs).unwrap();
 		(vs, fs)
-	};
+	}
+}
+
+pub fn get_layout_tex(
+	device: VkwDevice,
+	(vs, fs): &(VkwShader, VkwShader),
+	tex_capacity: u32,
+) -> VkwPipelineLayout {
 	let mut layout_create_infos: Vec<_> =
 		DescriptorSetLayoutCreateInfo::from_requirements(
 			vs.entry_point("main")
@@ -205,64 +567,149 @@ pub fn get_pipeline_tex(
 	binding.descriptor_type = DescriptorType::UniformBuffer;
 	let mut binding = layout_create_infos[1].bindings.get_mut(&0).unwrap();
 	binding.variable_descriptor_count = true;
-	binding.descriptor_count = tex_len;
+	binding.descriptor_count = tex_capacity;
 	let set_layouts = layout_create_infos
 		.into_iter()
 		.map(|desc| DescriptorSetLayout::new(device.clone(), desc))
 		.collect::<Result<Vec<_>, DescriptorSetLayoutCreationError>>()
 		.unwrap();
-	let pipeline_layout = PipelineLayout::new(
-		device.clone(),
+	PipelineLayout::new(
+		device,
 		PipelineLayoutCreateInfo {
 			set_layouts,
+			push_constant_ranges: vec![PushConstantRange {
+				stages: ShaderStages {
+					vertex: true,
+					fragment: true,
+					..ShaderStages::empty()
+				},
+				offset: 0,
+				size: PUSH_CONSTANT_SIZE,
+			}],
 			..Default::default()
 		},
 	)
-	.unwrap();
+	.unwrap()
+}
 
-	let subpass = Subpass::from(render_pass, 0).unwrap();
-	let pipeline = GraphicsPipeline::start()
+pub fn get_pipeline_tex(
+	render_pass: VkwRenderPass,
+	device: VkwDevice,
+	pipeline_layout: VkwPipelineLayout,
+	(vs, fs): (&VkwShader, &VkwShader),
+	key: PipelineKey,
+) -> Result<VkwPipeline, GraphicsPipelineCreationError> {
+	let srgb_target = render_pass.attachments()[0]
+		.format
+		.map_or(false, srgb_format);
+	let frag_spec = FragSpec {
+		srgb_target: srgb_target as u32,
+	};
+	let subpass_id = if key.oit { SUBPASS_ACCUM } else { 0 };
+	let subpass = Subpass::from(render_pass, subpass_id).unwrap();
+	let color_blend_state = if key.oit {
+		accum_blend_state()
+	} else {
+		let mut state = ColorBlendState::new(subpass.num_color_attachments());
+		for attachment in state.attachments.iter_mut() {
+			attachment.blend = key.blend.attachment_blend();
+			if key.mask.hidden() {
+				attachment.color_write_mask = ColorComponents::empty();
+			}
+		}
+		// integer attachment can not blend
+		state.attachments[1].blend = None;
+		if !key.picking {
+			state.attachments[1].color_write_mask = ColorComponents::empty();
+		}
+		state
+	};
+	let mut depth_stencil_state = DepthStencilState::simple_depth_test();
+	if let Some(depth) = depth_stencil_state.depth.as_mut() {
+		depth.write_enable = StateMode::Fixed(key.depth_write);
+	}
+	depth_stencil_state.stencil = key.mask.stencil_state();
+	GraphicsPipeline::start()
 		.vertex_input_state(BuffersDefinition::new().vertex::<VertexTex>())
 		.vertex_shader(vs.entry_point("main").unwrap(), ())
 		.input_assembly_state(
 			InputAssemblyState::new().topology(PrimitiveTopology::TriangleList),
 		)
 		.viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-		.fragment_shader(fs.entry_point("main").unwrap(), ())
-		.depth_stencil_state(DepthStencilState::simple_depth_test())
-		.color_blend_state(
-			ColorBlendState::new(subpass.num_color_attachments()).blend_alpha(),
-		)
+		.fragment_shader(fs.entry_point("main").unwrap(), frag_spec)
+		.depth_stencil_state(depth_stencil_state)
+		.color_blend_state(color_blend_state)
 		.render_pass(subpass)
 		.with_pipeline_layout(device, pipeline_layout)
-		.unwrap();
-	pipeline
 }
 
 pub fn window_size_dependent_setup(
 	render_pass: VkwRenderPass,
 	images: &VkwImages,
 	memalloc: VkwMemAlloc,
-) -> Vec<VkwFramebuffer> {
+	depth_format: Format,
+) -> SizeDependent {
 	let dimensions = images[0].dimensions().width_height();
 	let depth_buffer = ImageView::new_default(
-		AttachmentImage::transient(&memalloc, dimensions, Format::D16_UNORM)
+		AttachmentImage::transient(&memalloc, dimensions, depth_format)
 			.unwrap(),
 	)
 	.unwrap();
+	let accum = ImageView::new_default(
+		AttachmentImage::transient_input_attachment(
+			&memalloc,
+			dimensions,
+			Format::R16G16B16A16_SFLOAT,
+		)
+		.unwrap(),
+	)
+	.unwrap();
+	let reveal = ImageView::new_default(
+		AttachmentImage::transient_input_attachment(
+			&memalloc,
+			dimensions,
+			Format::R16_SFLOAT,
+		)
+		.unwrap(),
+	)
+	.unwrap();
+	let id = AttachmentImage::with_usage(
+		&memalloc,
+		dimensions,
+		Format::R32_UINT,
+		ImageUsage {
+			color_attachment: true,
+			transfer_src: true,
+			..ImageUsage::empty()
+		},
+	)
+	.unwrap();
+	let id_view = ImageView::new_default(id.clone()).unwrap();
 
-	images
+	let framebuffers = images
 		.iter()
 		.map(|image| {
 			let view = ImageView::new_default(image.clone()).unwrap();
 			Framebuffer::new(
 				render_pass.clone(),
 				FramebufferCreateInfo {
-					attachments: vec![view, depth_buffer.clone()],
+					attachments: vec![
+						view,
+						depth_buffer.clone(),
+						accum.clone(),
+						reveal.clone(),
+						id_view.clone(),
+					],
 					..Default::default()
 				},
 			)
 			.unwrap()
 		})
-		.collect::<Vec<_>>()
+		.collect::<Vec<_>>();
+	SizeDependent {
+		framebuffers,
+		accum,
+		reveal,
+		id,
+	}
 }
@@ -0,0 +1,114 @@
+// sampler settings of a texture, equal options share one sampler
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::sampler::{Sampler, SamplerCreateInfo, LOD_CLAMP_NONE};
+
+pub use vulkano::sampler::{
+	BorderColor, Filter, SamplerAddressMode, SamplerMipmapMode,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerOptions {
+	pub mag_filter: Filter,
+	pub min_filter: Filter,
+	pub mipmap_mode: SamplerMipmapMode,
+	// u, v
+	pub address_mode: [SamplerAddressMode; 2],
+	// max anisotropy, ignored if the device does not support it
+	pub anisotropy: Option<f32>,
+	pub lod_bias: f32,
+	// used by ClampToBorder
+	pub border_color: BorderColor,
+}
+
+impl Default for SamplerOptions {
+	fn default() -> Self {
+		Self {
+			mag_filter: Filter::Nearest,
+			min_filter: Filter::Nearest,
+			mipmap_mode: SamplerMipmapMode::Linear,
+			address_mode: [SamplerAddressMode::ClampToEdge; 2],
+			anisotropy: None,
+			lod_bias: 0.0,
+			border_color: BorderColor::FloatTransparentBlack,
+		}
+	}
+}
+
+impl SamplerOptions {
+	// crisp pixels at any zoom
+	pub fn pixel_art() -> Self {
+		Self {
+			mipmap_mode: SamplerMipmapMode::Nearest,
+			..Default::default()
+		}
+	}
+
+	pub fn linear() -> Self {
+		Self {
+			mag_filter: Filter::Linear,
+			min_filter: Filter::Linear,
+			..Default::default()
+		}
+	}
+
+	pub fn with_repeat(mut self) -> Self {
+		self.address_mode = [SamplerAddressMode::Repeat; 2];
+		self
+	}
+
+	pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+		self.anisotropy = Some(anisotropy);
+		self
+	}
+
+	fn create(&self, device: Arc<Device>) -> Arc<Sampler> {
+		let properties = device.physical_device().properties();
+		let anisotropy = if device.enabled_features().sampler_anisotropy {
+			self.anisotropy
+				.map(|x| x.clamp(1.0, properties.max_sampler_anisotropy))
+		} else {
+			None
+		};
+		let max_bias = properties.max_sampler_lod_bias;
+		let [u, v] = self.address_mode;
+		Sampler::new(
+			device,
+			SamplerCreateInfo {
+				mag_filter: self.mag_filter,
+				min_filter: self.min_filter,
+				mipmap_mode: self.mipmap_mode,
+				address_mode: [u, v, SamplerAddressMode::ClampToEdge],
+				mip_lod_bias: self.lod_bias.clamp(-max_bias, max_bias),
+				anisotropy,
+				lod: 0.0..=LOD_CLAMP_NONE,
+				border_color: self.border_color,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	}
+}
+
+// few distinct options are expected, so a linear search is enough
+#[derive(Default)]
+pub(crate) struct SamplerCache {
+	samplers: Vec<(SamplerOptions, Arc<Sampler>)>,
+}
+
+impl SamplerCache {
+	pub fn get(
+		&mut self,
+		device: Arc<Device>,
+		options: &SamplerOptions,
+	) -> Arc<Sampler> {
+		if let Some((_, sampler)) =
+			self.samplers.iter().find(|(x, _)| x == options)
+		{
+			return sampler.clone();
+		}
+		let sampler = options.create(device);
+		self.samplers.push((*options, sampler.clone()));
+		sampler
+	}
+}
@@ -0,0 +1,30 @@
+#version 450
+
+layout(input_attachment_index = 0, set = 0, binding = 0)
+	uniform subpassInput u_accum;
+layout(input_attachment_index = 1, set = 0, binding = 1)
+	uniform subpassInput u_reveal;
+
+layout(location = 0) out vec4 o_color;
+
+layout(constant_id = 0) const bool SRGB_TARGET = true;
+
+vec3 linear_to_srgb(vec3 c) {
+	return mix(
+		c * 12.92,
+		1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055,
+		greaterThan(c, vec3(0.0031308))
+	);
+}
+
+void main() {
+	float reveal = subpassLoad(u_reveal).r;
+	if (reveal >= 1.0) {
+		discard;
+	}
+	vec4 accum = subpassLoad(u_accum);
+	o_color = vec4(accum.xyz / max(accum.w, 1e-5), 1.0 - reveal);
+	if (!SRGB_TARGET) {
+		o_color.xyz = linear_to_srgb(o_color.xyz);
+	}
+}
@@ -4,11 +4,25 @@
 layout(location = 0) in vec4 f_color;
 layout(location = 1) in vec2 f_tex_coord;
 layout(location = 2) flat in int f_tex_layer;
+layout(location = 3) flat in uint f_id;
+layout(location = 4) flat in vec4 f_tint;
 
 layout(location = 0) out vec4 o_color;
+layout(location = 1) out uint o_id;
 
 layout(set = 1, binding = 0) uniform sampler2D tex[];
 
+// false when the swapchain has no srgb format and we must encode ourselves
+layout(constant_id = 0) const bool SRGB_TARGET = true;
+
+vec3 linear_to_srgb(vec3 c) {
+	return mix(
+		c * 12.92,
+		1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055,
+		greaterThan(c, vec3(0.0031308))
+	);
+}
+
 void main() {
 	if (f_tex_layer >= 0) {
 		o_color = texture(nonuniformEXT(tex[f_tex_layer]), f_tex_coord);
@@ -16,4 +30,10 @@ void main() {
 		o_color = vec4(0.0, 0.0, 0.0, 1.0);
 	}
 	o_color.xyz = f_color.w * f_color.xyz + (1.0 - f_color.w) * o_color.xyz;
+	o_color.xyz *= f_tint.xyz;
+	o_color.w *= f_tint.w;
+	o_id = f_id;
+	if (!SRGB_TARGET) {
+		o_color.xyz = linear_to_srgb(o_color.xyz);
+	}
 }
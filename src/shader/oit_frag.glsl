@@ -0,0 +1,35 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier: enable
+
+layout(location = 0) in vec4 f_color;
+layout(location = 1) in vec2 f_tex_coord;
+layout(location = 2) flat in int f_tex_layer;
+layout(location = 4) flat in vec4 f_tint;
+
+layout(location = 0) out vec4 o_accum;
+layout(location = 1) out float o_reveal;
+
+layout(set = 1, binding = 0) uniform sampler2D tex[];
+
+void main() {
+	vec4 color;
+	if (f_tex_layer >= 0) {
+		color = texture(nonuniformEXT(tex[f_tex_layer]), f_tex_coord);
+	} else {
+		color = vec4(0.0, 0.0, 0.0, 1.0);
+	}
+	color.xyz = f_color.w * f_color.xyz + (1.0 - f_color.w) * color.xyz;
+	color.xyz *= f_tint.xyz;
+	color.w *= f_tint.w;
+
+	// McGuire and Bavoil 2013, equation 10
+	float a = color.w;
+	float w = clamp(
+		pow(min(1.0, a * 10.0) + 0.01, 3.0) * 1e8 *
+			pow(1.0 - gl_FragCoord.z * 0.9, 3.0),
+		1e-2,
+		3e3
+	);
+	o_accum = vec4(color.xyz * a, a) * w;
+	o_reveal = a;
+}
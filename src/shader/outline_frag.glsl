@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) out vec4 o_color;
+
+layout(push_constant) uniform Outline {
+	vec4 color;
+	vec2 offset;
+} outline;
+
+layout(constant_id = 0) const bool SRGB_TARGET = true;
+
+vec3 srgb_to_linear(vec3 c) {
+	return mix(
+		c / 12.92,
+		pow((c + 0.055) / 1.055, vec3(2.4)),
+		greaterThan(c, vec3(0.04045))
+	);
+}
+
+// outline color is srgb like face colors
+void main() {
+	o_color = outline.color;
+	if (SRGB_TARGET) {
+		o_color.xyz = srgb_to_linear(o_color.xyz);
+	}
+}
@@ -0,0 +1,86 @@
+#version 450
+
+layout(location = 0) in vec4 pos;
+layout(location = 4) in uint vid;
+
+layout(set = 0, binding = 0) uniform Data {
+	mat4 view;
+	mat4 proj;
+} uniforms;
+
+// matches model_data.rs
+struct ModelData {
+	mat4 transform;
+	vec3 tint;
+	float opacity;
+	vec3 uv_u;
+	uint id;
+	vec3 uv_v;
+	uint morph_offset;
+	uint morph_vertices;
+	uint morph_targets;
+	uint weight_offset;
+	uint skin_offset;
+	uint skin_vertices;
+	uint joint_offset;
+	uint joint_count;
+	uint _pad;
+};
+
+layout(set = 0, binding = 1) readonly buffer Models {
+	ModelData models[];
+};
+
+struct MorphDelta {
+	vec4 pos;
+	vec4 color;
+};
+
+layout(set = 0, binding = 2) readonly buffer Morphs {
+	MorphDelta deltas[];
+};
+
+layout(set = 0, binding = 3) readonly buffer Weights {
+	float weights[];
+};
+
+struct SkinVertex {
+	uvec4 joints;
+	vec4 weights;
+};
+
+layout(set = 0, binding = 4) readonly buffer Skins {
+	SkinVertex skins[];
+};
+
+layout(set = 0, binding = 5) readonly buffer Joints {
+	mat4 joints[];
+};
+
+layout(push_constant) uniform Outline {
+	vec4 color;
+	vec2 offset;
+} outline;
+
+void main() {
+	ModelData model = models[gl_InstanceIndex];
+	vec4 p = pos;
+	if (vid < model.morph_vertices) {
+		for (uint t = 0; t < model.morph_targets; t++) {
+			float w = weights[model.weight_offset + t];
+			uint idx = model.morph_offset + t * model.morph_vertices + vid;
+			p += deltas[idx].pos * w;
+		}
+	}
+	// morph first, then skin
+	if (vid < model.skin_vertices) {
+		SkinVertex skin = skins[model.skin_offset + vid];
+		mat4 m = mat4(0.0);
+		for (int i = 0; i < 4; i++) {
+			m += joints[model.joint_offset + skin.joints[i]] * skin.weights[i];
+		}
+		p = m * p;
+	}
+	gl_Position = uniforms.proj * uniforms.view * model.transform * p;
+	gl_Position.xy += outline.offset * gl_Position.w;
+}
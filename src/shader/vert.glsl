@@ -4,19 +4,105 @@ layout(location = 0) in vec4 pos;
 layout(location = 1) in vec4 color;
 layout(location = 2) in vec2 tex_coord;
 layout(location = 3) in int tex_layer;
+layout(location = 4) in uint vid;
 
 layout(location = 0) out vec4 f_color;
 layout(location = 1) out vec2 f_tex_coord;
 layout(location = 2) out int f_tex_layer;
+layout(location = 3) out uint f_id;
+// linear tint and opacity, applied after texturing
+layout(location = 4) out vec4 f_tint;
 
 layout(set = 0, binding = 0) uniform Data {
 	mat4 view;
 	mat4 proj;
 } uniforms;
 
+// matches model_data.rs
+struct ModelData {
+	mat4 transform;
+	vec3 tint;
+	float opacity;
+	vec3 uv_u;
+	uint id;
+	vec3 uv_v;
+	uint morph_offset;
+	uint morph_vertices;
+	uint morph_targets;
+	uint weight_offset;
+	uint skin_offset;
+	uint skin_vertices;
+	uint joint_offset;
+	uint joint_count;
+	uint _pad;
+};
+
+layout(set = 0, binding = 1) readonly buffer Models {
+	ModelData models[];
+};
+
+struct MorphDelta {
+	vec4 pos;
+	vec4 color;
+};
+
+layout(set = 0, binding = 2) readonly buffer Morphs {
+	MorphDelta deltas[];
+};
+
+layout(set = 0, binding = 3) readonly buffer Weights {
+	float weights[];
+};
+
+struct SkinVertex {
+	uvec4 joints;
+	vec4 weights;
+};
+
+layout(set = 0, binding = 4) readonly buffer Skins {
+	SkinVertex skins[];
+};
+
+layout(set = 0, binding = 5) readonly buffer Joints {
+	mat4 joints[];
+};
+
+// face colors are authored in srgb, blending happens in linear space
+vec3 srgb_to_linear(vec3 c) {
+	return mix(
+		c / 12.92,
+		pow((c + 0.055) / 1.055, vec3(2.4)),
+		greaterThan(c, vec3(0.04045))
+	);
+}
+
 void main() {
-	gl_Position = uniforms.proj * uniforms.view * pos;
-	f_color = color;
-	f_tex_coord = tex_coord;
+	ModelData model = models[gl_InstanceIndex];
+	vec4 p = pos;
+	vec4 c = color;
+	if (vid < model.morph_vertices) {
+		for (uint t = 0; t < model.morph_targets; t++) {
+			float w = weights[model.weight_offset + t];
+			uint idx = model.morph_offset + t * model.morph_vertices + vid;
+			p += deltas[idx].pos * w;
+			c += deltas[idx].color * w;
+		}
+	}
+	c = clamp(c, 0.0, 1.0);
+	// morph first, then skin
+	if (vid < model.skin_vertices) {
+		SkinVertex skin = skins[model.skin_offset + vid];
+		mat4 m = mat4(0.0);
+		for (int i = 0; i < 4; i++) {
+			m += joints[model.joint_offset + skin.joints[i]] * skin.weights[i];
+		}
+		p = m * p;
+	}
+	gl_Position = uniforms.proj * uniforms.view * model.transform * p;
+	f_color = vec4(srgb_to_linear(c.xyz), c.w);
+	f_tint = vec4(srgb_to_linear(model.tint), model.opacity);
+	vec3 uv = vec3(tex_coord, 1.0);
+	f_tex_coord = vec2(dot(model.uv_u, uv), dot(model.uv_v, uv));
 	f_tex_layer = tex_layer;
+	f_id = model.id;
 }
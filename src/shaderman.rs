@@ -0,0 +1,147 @@
+// user provided spirv programs, selected per model
+// vertex shaders take VertexTex input and ModelData at set 0 binding 1,
+// fragment shaders output color at location 0 and model id at location 1,
+// both stages can read a push constant block of up to 128 bytes
+use std::collections::HashMap;
+use vulkano::pipeline::graphics::GraphicsPipelineCreationError;
+use vulkano::shader::{ShaderCreationError, ShaderModule};
+
+use crate::helper::*;
+use crate::rmod::PUSH_CONSTANT_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId(u32);
+
+impl ShaderId {
+	pub const BUILTIN: Self = Self(0);
+}
+
+impl Default for ShaderId {
+	fn default() -> Self {
+		Self::BUILTIN
+	}
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+	Creation(ShaderCreationError),
+	NoEntryPoint,
+	PushConstantTooLarge(u32),
+	Pipeline(GraphicsPipelineCreationError),
+	Io(std::io::Error),
+	Glsl(String),
+	Unknown(ShaderId),
+}
+
+impl std::fmt::Display for ShaderError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Creation(e) => write!(f, "shader creation failed: {}", e),
+			Self::NoEntryPoint => write!(f, "no main entry point"),
+			Self::PushConstantTooLarge(size) => write!(
+				f,
+				"push constant block is {} bytes, max {}",
+				size, PUSH_CONSTANT_SIZE,
+			),
+			Self::Pipeline(e) => write!(f, "incompatible shader: {}", e),
+			Self::Io(e) => write!(f, "{}", e),
+			Self::Glsl(e) => write!(f, "glsl: {}", e),
+			Self::Unknown(id) => write!(f, "unknown shader {:?}", id),
+		}
+	}
+}
+
+impl std::error::Error for ShaderError {}
+
+// Safety: spirv must be a valid spirv module as defined by the vulkan
+// spec, vulkano only checks the header and capabilities
+pub unsafe fn load_shader(
+	device: VkwDevice,
+	spirv: &[u8],
+) -> Result<VkwShader, ShaderError> {
+	let shader = ShaderModule::from_bytes(device, spirv)
+		.map_err(ShaderError::Creation)?;
+	let entry = shader.entry_point("main").ok_or(ShaderError::NoEntryPoint)?;
+	if let Some(range) = entry.push_constant_requirements() {
+		let size = range.offset + range.size;
+		if size > PUSH_CONSTANT_SIZE {
+			return Err(ShaderError::PushConstantTooLarge(size));
+		}
+	}
+	Ok(shader)
+}
+
+pub struct Shaderman {
+	device: VkwDevice,
+	shaders: HashMap<ShaderId, (VkwShader, VkwShader)>,
+	// replaces the fragment stage in oit accumulation
+	pub oit_fs: VkwShader,
+	id_alloc: u32,
+}
+
+impl Shaderman {
+	pub fn new(
+		device: VkwDevice,
+		builtin: (VkwShader, VkwShader),
+		oit_fs: VkwShader,
+	) -> Self {
+		let mut shaders = HashMap::new();
+		shaders.insert(ShaderId::BUILTIN, builtin);
+		Self {
+			device,
+			shaders,
+			oit_fs,
+			id_alloc: 1,
+		}
+	}
+
+	pub fn builtin(&self) -> &(VkwShader, VkwShader) {
+		&self.shaders[&ShaderId::BUILTIN]
+	}
+
+	pub fn contains(&self, id: ShaderId) -> bool {
+		self.shaders.contains_key(&id)
+	}
+
+	// unknown ids fall back to builtin
+	pub fn get(&self, id: ShaderId) -> &(VkwShader, VkwShader) {
+		self.shaders.get(&id).unwrap_or_else(|| self.builtin())
+	}
+
+	// Safety: see load_shader
+	pub unsafe fn register(
+		&mut self,
+		vs: &[u8],
+		fs: &[u8],
+	) -> Result<ShaderId, ShaderError> {
+		let vs = load_shader(self.device.clone(), vs)?;
+		let fs = load_shader(self.device.clone(), fs)?;
+		let id = ShaderId(self.id_alloc);
+		self.id_alloc += 1;
+		self.shaders.insert(id, (vs, fs));
+		Ok(id)
+	}
+
+	// returns the replaced pair
+	// Safety: see load_shader
+	pub unsafe fn replace(
+		&mut self,
+		id: ShaderId,
+		vs: &[u8],
+		fs: &[u8],
+	) -> Result<Option<(VkwShader, VkwShader)>, ShaderError> {
+		let vs = load_shader(self.device.clone(), vs)?;
+		let fs = load_shader(self.device.clone(), fs)?;
+		Ok(self.shaders.insert(id, (vs, fs)))
+	}
+
+	pub fn restore(&mut self, id: ShaderId, shaders: (VkwShader, VkwShader)) {
+		self.shaders.insert(id, shaders);
+	}
+
+	pub fn remove(&mut self, id: ShaderId) {
+		if id != ShaderId::BUILTIN {
+			self.shaders.remove(&id);
+		}
+	}
+}
@@ -1,13 +1,42 @@
 use std::path::Path;
 use image::ImageBuffer;
 
+use crate::sampler::SamplerOptions;
+
+pub use vulkano::format::Format;
+
 pub struct Teximg {
 	pub color: bool,
+	// srgb encoded data is linearized by sampler,
+	// set to false for normal maps and other data textures.
+	// ignored for luma, which is always linear
+	pub srgb: bool,
+	// generate a full mip chain on upload
+	pub mipmaps: bool,
+	// single level image that accepts region updates
+	pub dynamic: bool,
+	// may be dropped when over the texture budget
+	pub evictable: bool,
+	pub sampler: SamplerOptions,
 	pub dim: [u32; 2],
 	// rgba8
 	pub data: Vec<u8>,
 }
 
+// an uploaded texture, see Renderer::tex_info
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TexInfo {
+	pub dim: [u32; 2],
+	pub format: Format,
+	pub color: bool,
+	pub srgb: bool,
+	pub mip_levels: u32,
+	pub dynamic: bool,
+	pub sampler: SamplerOptions,
+	// approximate device memory in bytes
+	pub memory: u64,
+}
+
 pub type RgbaImage = ImageBuffer<image::Rgba<u8>, Vec<u8>>;
 pub type LumaImage = ImageBuffer<image::Luma<u8>, Vec<u8>>;
 
@@ -16,6 +45,11 @@ impl Teximg {
 		let dim = image_buffer.dimensions();
 		Self {
 			color: true,
+			srgb: true,
+			mipmaps: true,
+			dynamic: false,
+			evictable: false,
+			sampler: Default::default(),
 			dim: [dim.0, dim.1],
 			data: image_buffer.into_vec(),
 		}
@@ -25,6 +59,11 @@ impl Teximg {
 		let dim = image_buffer.dimensions();
 		Self {
 			color: false,
+			srgb: false,
+			mipmaps: true,
+			dynamic: false,
+			evictable: false,
+			sampler: Default::default(),
 			dim: [dim.0, dim.1],
 			data: image_buffer.into_vec(),
 		}
@@ -33,6 +72,11 @@ impl Teximg {
 	pub fn luma_filled(dim: [u32; 2], value: [u8; 4]) -> Self {
 		Self {
 			color: false,
+			srgb: false,
+			mipmaps: true,
+			dynamic: false,
+			evictable: false,
+			sampler: Default::default(),
 			dim,
 			data: value
 				.into_iter()
@@ -42,6 +86,96 @@ impl Teximg {
 		}
 	}
 
+	pub fn with_srgb(mut self, srgb: bool) -> Self {
+		self.srgb = srgb;
+		self
+	}
+
+	pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+		self.mipmaps = mipmaps;
+		self
+	}
+
+	// mipmaps are ignored for dynamic textures
+	pub fn with_dynamic(mut self, dynamic: bool) -> Self {
+		self.dynamic = dynamic;
+		self
+	}
+
+	// see Renderer::set_tex_budget
+	pub fn with_evictable(mut self, evictable: bool) -> Self {
+		self.evictable = evictable;
+		self
+	}
+
+	pub fn with_sampler(mut self, sampler: SamplerOptions) -> Self {
+		self.sampler = sampler;
+		self
+	}
+
+	pub fn channels(&self) -> usize {
+		if self.color {
+			4
+		} else {
+			1
+		}
+	}
+
+	// levels of a full chain down to 1x1
+	pub fn mip_levels(&self) -> u32 {
+		32 - self.dim[0].max(self.dim[1]).max(1).leading_zeros()
+	}
+
+	// half size, 2x2 box filter, srgb color is averaged in linear space
+	pub fn downsample(&self) -> Self {
+		let [w, h] = self.dim;
+		let dim = [(w / 2).max(1), (h / 2).max(1)];
+		let channels = self.channels();
+		let linear = |c: usize, x: u8| {
+			let x = x as f32 / 255.0;
+			if self.srgb && self.color && c < 3 {
+				srgb_to_linear(x)
+			} else {
+				x
+			}
+		};
+		let mut data = Vec::with_capacity((dim[0] * dim[1]) as usize * channels);
+		for y in 0..dim[1] {
+			for x in 0..dim[0] {
+				for c in 0..channels {
+					let mut sum = 0.0;
+					for [dx, dy] in [[0, 0], [1, 0], [0, 1], [1, 1]] {
+						let sx = (x * 2 + dx).min(w - 1);
+						let sy = (y * 2 + dy).min(h - 1);
+						let idx = (sy * w + sx) as usize * channels + c;
+						sum += linear(c, self.data[idx]);
+					}
+					let mut value = sum / 4.0;
+					if self.srgb && self.color && c < 3 {
+						value = linear_to_srgb(value);
+					}
+					data.push((value * 255.0).round() as u8);
+				}
+			}
+		}
+		Self {
+			dim,
+			data,
+			..*self
+		}
+	}
+
+	// full chain starting with self
+	pub fn mip_chain(self) -> Vec<Self> {
+		let len = self.mip_levels();
+		let mut levels = vec![self];
+		for _ in 1..len {
+			let next = levels.last().unwrap().downsample();
+			levels.push(next);
+		}
+		levels
+	}
+
 	pub fn preset_rgb565() -> Self {
 		let image = ImageBuffer::from_fn(1024, 64, |x, y| {
 			image::Rgba::from([
@@ -55,11 +189,19 @@ impl Teximg {
 	}
 
 	pub fn load<P: AsRef<Path>>(path: P, color: bool) -> Self {
-		if color {
-			Self::from_rgba(image::open(path).unwrap().into_rgba8())
+		Self::try_load(path, color).unwrap()
+	}
+
+	pub fn try_load<P: AsRef<Path>>(
+		path: P,
+		color: bool,
+	) -> Result<Self, image::ImageError> {
+		let image = image::open(path)?;
+		Ok(if color {
+			Self::from_rgba(image.into_rgba8())
 		} else {
-			Self::from_luma(image::open(path).unwrap().into_luma8())
-		}
+			Self::from_luma(image.into_luma8())
+		})
 	}
 
 	pub fn save<P: AsRef<Path>>(&self, path: P) {
@@ -77,6 +219,22 @@ impl Teximg {
 	}
 }
 
+fn srgb_to_linear(x: f32) -> f32 {
+	if x <= 0.04045 {
+		x / 12.92
+	} else {
+		((x + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn linear_to_srgb(x: f32) -> f32 {
+	if x <= 0.0031308 {
+		x * 12.92
+	} else {
+		1.055 * x.powf(1.0 / 2.4) - 0.055
+	}
+}
+
 pub fn rgb_to_16uv(rgb: [u8; 3]) -> [f32; 2] {
 	let xr = (rgb[0] / 8) as f32 / 32.0;
 	let xb = rgb[2] as f32 / 256.0 / 32.0;
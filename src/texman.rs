@@ -1,58 +1,350 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{BufferImageCopy, CopyBufferToImageInfo};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
-use vulkano::format::Format;
+use vulkano::device::DeviceOwned;
+use vulkano::format::{Format, NumericType};
 use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
-use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
-use vulkano::sampler::{ComponentMapping, Sampler, SamplerCreateInfo};
+use vulkano::image::{
+	ImageAccess, ImageCreateFlags, ImageDimensions, ImageLayout,
+	ImageSubresourceLayers, ImageUsage, ImmutableImage, MipmapsCount,
+	StorageImage,
+};
+use vulkano::sampler::ComponentMapping;
 
 use crate::helper::*;
-use crate::teximg::Teximg;
+use crate::sampler::{SamplerCache, SamplerOptions};
+use crate::teximg::{TexInfo, Teximg};
+use crate::texture_ref::TextureRef;
 
-pub struct Texman {
-	// we don't use outer id allocator
-	// to allow creating model in advance of uploading that texture
-	// user is responsible for preventing outer id collision.
-	pub mapper: HashMap<i32, i32>,
+// upper bound of the texture array, further clamped by device limits
+pub const TEX_CAPACITY: u32 = 4096;
 
-	// pending remove_list record inner id only,
-	// when an inner get pushed, it must have already been deleted from mapper
-	// the removal is executed in compile_set
-	remove_list: Vec<i32>,
-	id_alloc: i32,
+// uploaded by renderer, bound to every empty slot
+pub const PLACEHOLDER: i32 = -2;
 
-	image_views: Vec<VkwImageView>,
-	dirty: bool,
+// ids of texture refs are allocated from here
+pub const AUTO_ID: i32 = 1 << 30;
+
+// frames between descriptor set rebuilds for changed textures,
+// every rebuild rewrites all slots
+pub const REBUILD_INTERVAL: u64 = 4;
+
+// outer id to slot, shared with models to compile faces
+// a slot keeps its index while the id is uploaded or referenced by a
+// compiled model, so vertices never need remapping
+pub struct Slots {
+	mapper: HashMap<i32, i32>,
+	// indexed by slot, none for free slots.
+	// the length is the variable descriptor count
+	slots: Vec<Option<Slot>>,
+	free: Vec<i32>,
+	// a slot taken from free still binds its old texture
+	// until the set is rebuilt
+	reused: bool,
+	capacity: i32,
+	// sprite id to atlas page id and uv rect
+	sprites: HashMap<i32, (i32, [f32; 4])>,
+	// atlas page id to its sprite ids
+	pages: HashMap<i32, Vec<i32>>,
+}
+
+struct Slot {
+	outer: i32,
+	// compiled models using it
+	refs: u32,
+	// uploaded or reserved by a texture ref, until removed
+	held: bool,
 }
 
-impl Default for Texman {
-	fn default() -> Self {
+pub type Mapper = Rc<RefCell<Slots>>;
+
+impl Slots {
+	fn new(capacity: u32) -> Self {
 		Self {
-			mapper: Default::default(),
-			remove_list: Vec::new(),
-			id_alloc: 0,
-			image_views: Vec::new(),
-			dirty: true,
+			mapper: HashMap::new(),
+			slots: Vec::new(),
+			free: Vec::new(),
+			reused: false,
+			capacity: capacity as i32,
+			sprites: HashMap::new(),
+			pages: HashMap::new(),
+		}
+	}
+
+	pub fn get(&self, outer: i32) -> Option<i32> {
+		self.mapper.get(&outer).copied()
+	}
+
+	// slot of an uploaded id, none after remove
+	fn get_held(&self, outer: i32) -> Option<i32> {
+		let slot = self.get(outer)?;
+		match &self.slots[slot as usize] {
+			Some(x) if x.held => Some(slot),
+			_ => None,
+		}
+	}
+
+	// unknown ids get a slot bound to the placeholder until uploaded,
+	// none if the array is full. the slot is freed again unless
+	// held or acquired
+	pub fn alloc(&mut self, outer: i32) -> Option<i32> {
+		if let Some(slot) = self.mapper.get(&outer) {
+			return Some(*slot);
+		}
+		let slot = match self.free.pop() {
+			Some(slot) => {
+				self.reused = true;
+				slot
+			}
+			None if (self.slots.len() as i32) < self.capacity => {
+				self.slots.push(None);
+				self.slots.len() as i32 - 1
+			}
+			None => return None,
+		};
+		self.slots[slot as usize] = Some(Slot {
+			outer,
+			refs: 0,
+			held: false,
+		});
+		self.mapper.insert(outer, slot);
+		Some(slot)
+	}
+
+	fn hold(&mut self, outer: i32) -> Option<i32> {
+		let slot = self.alloc(outer)?;
+		self.slots[slot as usize].as_mut().unwrap().held = true;
+		Some(slot)
+	}
+
+	// sprites of a page go away with it
+	fn unhold(&mut self, outer: i32) -> Option<i32> {
+		let slot = self.get_held(outer)?;
+		for id in self.pages.remove(&outer).unwrap_or_default() {
+			// unless re-added on a later page
+			if self.sprites.get(&id).map(|x| x.0) == Some(outer) {
+				self.sprites.remove(&id);
+			}
 		}
+		self.slots[slot as usize].as_mut().unwrap().held = false;
+		self.try_free(slot);
+		Some(slot)
+	}
+
+	// one reference per compiled model, slots are distinct
+	pub fn acquire(&mut self, slots: &[i32]) {
+		for slot in slots {
+			self.slots[*slot as usize].as_mut().unwrap().refs += 1;
+		}
+	}
+
+	pub fn release(&mut self, slots: &[i32]) {
+		for slot in slots {
+			self.slots[*slot as usize].as_mut().unwrap().refs -= 1;
+			self.try_free(*slot);
+		}
+	}
+
+	fn try_free(&mut self, slot: i32) {
+		let entry = &mut self.slots[slot as usize];
+		if let Some(x) = entry.as_ref().filter(|x| x.refs == 0 && !x.held) {
+			self.mapper.remove(&x.outer);
+			*entry = None;
+			self.free.push(slot);
+		}
+	}
+
+	fn add_sprites(&mut self, page: i32, sprites: Vec<(i32, [f32; 4])>) {
+		let mut ids = Vec::with_capacity(sprites.len());
+		for (id, rect) in sprites {
+			self.sprites.insert(id, (page, rect));
+			ids.push(id);
+		}
+		self.pages.insert(page, ids);
+	}
+
+	pub fn sprite(&self, outer: i32) -> Option<(i32, [f32; 4])> {
+		self.sprites.get(&outer).copied()
 	}
 }
 
-// TODO: mutable image
-fn create_image_view(
-	image: Teximg,
-	memalloc: VkwMemAlloc,
+pub struct Texman {
+	// user ids are not allocated
+	// to allow creating model in advance of uploading that texture
+	// user is responsible for preventing outer id collision,
+	// ids from AUTO_ID are reserved for texture refs
+	pub mapper: Mapper,
+	handles: Vec<TextureRef>,
+	auto_id: i32,
+
+	// indexed by slot, none for free or reserved slots
+	textures: Vec<Option<Texture>>,
+	samplers: SamplerCache,
+	// recorded into the open upload batch, with outer id
+	batch: Vec<(i32, Texture)>,
+	// submitted batches, moved into slots once the fence is signaled
+	in_flight: Vec<(VkwFence, Vec<(i32, Texture)>)>,
+	dirty: bool,
+	// frame and slot count of the last built set, none before the first
+	built: Option<(u64, usize)>,
+
+	// bytes, evictable textures are dropped when exceeded
+	pub budget: Option<u64>,
+	memory: u64,
+	frame: u64,
+	// outer ids dropped by budget, slots stay reserved for re-upload
+	pub evicted: Vec<i32>,
+}
+
+#[derive(Clone)]
+pub struct Texture {
+	view: VkwImageView,
+	sampler: SamplerOptions,
+	color: bool,
+	dim: [u32; 2],
+	// dynamic textures only
+	image: Option<Arc<StorageImage>>,
+	memory: u64,
+	evictable: bool,
+	// frame it was last drawn in
+	last_used: u64,
+}
+
+// all mip levels, ignoring alignment
+fn memory_of(dim: [u32; 2], color: bool, mip_levels: u32) -> u64 {
+	let texel = if color { 4 } else { 1 };
+	(0..mip_levels)
+		.map(|level| {
+			let [w, h] = dim.map(|x| (x >> level).max(1));
+			w as u64 * h as u64 * texel
+		})
+		.sum()
+}
+
+// descriptor count limits without update-after-bind
+pub fn capacity(device: &VkwDevice) -> u32 {
+	let properties = device.physical_device().properties();
+	TEX_CAPACITY
+		.min(properties.max_per_stage_descriptor_samplers)
+		.min(properties.max_per_stage_descriptor_sampled_images)
+}
+
+// blit based mip generation needs these features
+fn can_blit(memalloc: &VkwMemAlloc, format: Format) -> bool {
+	let physical_device = memalloc.device().physical_device();
+	let features =
+		physical_device.format_properties(format).optimal_tiling_features;
+	features.blit_src
+		&& features.blit_dst
+		&& features.sampled_image_filter_linear
+}
+
+// every level is copied from one staging buffer
+fn upload_levels(
+	levels: Vec<Teximg>,
+	dimensions: ImageDimensions,
+	format: Format,
+	memalloc: &VkwMemAlloc,
 	builder: &mut VkwCommandBuilder,
-) -> VkwImageView {
-	let dimensions = ImageDimensions::Dim2d {
-		width: image.dim[0],
-		height: image.dim[1],
-		array_layers: 1,
-	};
-	let format = if image.color {
-		Format::R8G8B8A8_SRGB
-	} else {
-		Format::R8_UNORM
+) -> Arc<ImmutableImage> {
+	let (image, init) = ImmutableImage::uninitialized(
+		memalloc,
+		dimensions,
+		format,
+		MipmapsCount::Specific(levels.len() as u32),
+		// source of download_tex
+		ImageUsage {
+			transfer_src: true,
+			transfer_dst: true,
+			sampled: true,
+			..ImageUsage::empty()
+		},
+		ImageCreateFlags::empty(),
+		ImageLayout::ShaderReadOnlyOptimal,
+		memalloc.device().active_queue_family_indices().iter().copied(),
+	)
+	.unwrap();
+	let mut regions = Vec::new();
+	let mut offset = 0;
+	for (level, image) in levels.iter().enumerate() {
+		regions.push(BufferImageCopy {
+			buffer_offset: offset,
+			image_subresource: ImageSubresourceLayers {
+				mip_level: level as u32,
+				..init.subresource_layers()
+			},
+			image_extent: [image.dim[0], image.dim[1], 1],
+			..Default::default()
+		});
+		offset += image.data.len() as u64;
+	}
+	let data = levels.into_iter().flat_map(|x| x.data).collect();
+	let buffer = staging_buffer(memalloc, data);
+	builder
+		.copy_buffer_to_image(CopyBufferToImageInfo {
+			regions: regions.into(),
+			..CopyBufferToImageInfo::buffer_image(buffer, init)
+		})
+		.unwrap();
+	image
+}
+
+fn staging_buffer(
+	memalloc: &VkwMemAlloc,
+	data: Vec<u8>,
+) -> Arc<CpuAccessibleBuffer<[u8]>> {
+	CpuAccessibleBuffer::from_iter(
+		memalloc,
+		BufferUsage {
+			transfer_src: true,
+			..BufferUsage::empty()
+		},
+		false,
+		data,
+	)
+	.unwrap()
+}
+
+fn region_copy(
+	memalloc: &VkwMemAlloc,
+	image: Arc<StorageImage>,
+	offset: [u32; 2],
+	data: Teximg,
+) -> CopyBufferToImageInfo {
+	let region = BufferImageCopy {
+		image_subresource: image.subresource_layers(),
+		image_offset: [offset[0], offset[1], 0],
+		image_extent: [data.dim[0], data.dim[1], 1],
+		..Default::default()
 	};
-	let component_mapping = if image.color {
+	CopyBufferToImageInfo {
+		regions: [region].into(),
+		..CopyBufferToImageInfo::buffer_image(
+			staging_buffer(memalloc, data.data),
+			image,
+		)
+	}
+}
+
+// luma is swizzled into alpha which stays linear, and R8_SRGB is
+// often unsupported, so srgb only applies to color
+fn format_of(image: &Teximg) -> Format {
+	match (image.color, image.srgb) {
+		(true, true) => Format::R8G8B8A8_SRGB,
+		(true, false) => Format::R8G8B8A8_UNORM,
+		(false, _) => Format::R8_UNORM,
+	}
+}
+
+fn create_view<I>(image: Arc<I>, color: bool) -> VkwImageView
+where
+	I: ImageAccess + 'static,
+{
+	let component_mapping = if color {
 		ComponentMapping::default()
 	} else {
 		use vulkano::sampler::ComponentSwizzle as Sw;
@@ -63,15 +355,6 @@ fn create_image_view(
 			a: Sw::Red,
 		}
 	};
-	let image = ImmutableImage::from_iter(
-		&memalloc,
-		image.data.into_iter(),
-		dimensions,
-		MipmapsCount::One,
-		format,
-		builder,
-	)
-	.unwrap();
 	ImageView::new(
 		image.clone(),
 		ImageViewCreateInfo {
@@ -83,81 +366,432 @@ fn create_image_view(
 	.unwrap()
 }
 
+// single level storage image, written by region copies
+fn create_dynamic(
+	image: Teximg,
+	memalloc: &VkwMemAlloc,
+	builder: &mut VkwCommandBuilder,
+) -> Arc<StorageImage> {
+	let storage = StorageImage::with_usage(
+		memalloc,
+		ImageDimensions::Dim2d {
+			width: image.dim[0],
+			height: image.dim[1],
+			array_layers: 1,
+		},
+		format_of(&image),
+		ImageUsage {
+			transfer_src: true,
+			transfer_dst: true,
+			sampled: true,
+			..ImageUsage::empty()
+		},
+		ImageCreateFlags::empty(),
+		memalloc.device().active_queue_family_indices().iter().copied(),
+	)
+	.unwrap();
+	let copy = region_copy(memalloc, storage.clone(), [0, 0], image);
+	builder.copy_buffer_to_image(copy).unwrap();
+	storage
+}
+
+// levels are a single base image or a precomputed mip chain
+// user supplied mip chains are checked before they are queued
+pub fn check_levels(levels: &[Teximg]) -> Result<(), String> {
+	let image = match levels.first() {
+		Some(x) => x,
+		None => return Err("no levels".to_string()),
+	};
+	let max = image.mip_levels();
+	if levels.len() as u32 > max {
+		return Err(format!("{} levels, max {}", levels.len(), max));
+	}
+	for (idx, level) in levels.iter().enumerate() {
+		let expect = image.dim.map(|x| (x >> idx).max(1));
+		if level.dim != expect || level.color != image.color {
+			return Err(format!("bad size or format of level {}", idx));
+		}
+		let len = (expect[0] * expect[1]) as usize * level.channels();
+		if level.data.len() != len {
+			return Err(format!("bad data length of level {}", idx));
+		}
+	}
+	Ok(())
+}
+
+// levels passed check_levels
+fn create_immutable(
+	mut levels: Vec<Teximg>,
+	memalloc: &VkwMemAlloc,
+	builder: &mut VkwCommandBuilder,
+) -> Arc<ImmutableImage> {
+	let image = &levels[0];
+	let dimensions = ImageDimensions::Dim2d {
+		width: image.dim[0],
+		height: image.dim[1],
+		array_layers: 1,
+	};
+	let format = format_of(image);
+	let generate = levels.len() == 1 && image.mipmaps;
+	if generate && can_blit(memalloc, format) {
+		// vulkano fills the chain with linear blits
+		let image = levels.pop().unwrap();
+		ImmutableImage::from_iter(
+			memalloc,
+			image.data.into_iter(),
+			dimensions,
+			MipmapsCount::Log2,
+			format,
+			builder,
+		)
+		.unwrap()
+	} else {
+		if generate {
+			levels = levels.pop().unwrap().mip_chain();
+		}
+		upload_levels(levels, dimensions, format, memalloc, builder)
+	}
+}
+
 impl Texman {
+	pub fn new(capacity: u32) -> Self {
+		Self {
+			mapper: Rc::new(RefCell::new(Slots::new(capacity))),
+			handles: Vec::new(),
+			auto_id: AUTO_ID,
+			textures: Vec::new(),
+			samplers: Default::default(),
+			batch: Vec::new(),
+			in_flight: Vec::new(),
+			dirty: true,
+			built: None,
+			budget: None,
+			memory: 0,
+			frame: 0,
+			evicted: Vec::new(),
+		}
+	}
+
+	pub fn capacity(&self) -> u32 {
+		self.mapper.borrow().capacity as u32
+	}
+
+	// recorded into the upload batch builder,
+	// re-uploading an id replaces the texture in its slot when resident
 	pub fn upload(
 		&mut self,
-		image: Teximg,
+		levels: Vec<Teximg>,
 		id: i32,
 		memalloc: VkwMemAlloc,
 		builder: &mut VkwCommandBuilder,
 	) {
-		if let Some(id_inner) = self.mapper.get(&id) {
-			self.remove_list.push(*id_inner);
+		if self.mapper.borrow_mut().hold(id).is_none() {
+			eprintln!("ERROR: texture array is full, skip {}", id);
+			return;
+		}
+		let base = &levels[0];
+		let (sampler, color, dim) = (base.sampler, base.color, base.dim);
+		let evictable = base.evictable;
+		let (view, image) = if base.dynamic {
+			let image = levels.into_iter().next().unwrap();
+			let image = create_dynamic(image, &memalloc, builder);
+			(create_view(image.clone(), color), Some(image))
+		} else {
+			let image = create_immutable(levels, &memalloc, builder);
+			(create_view(image, color), None)
+		};
+		let memory = memory_of(dim, color, view.image().mip_levels());
+		let texture = Texture {
+			view,
+			sampler,
+			color,
+			dim,
+			image,
+			memory,
+			evictable,
+			last_used: 0,
+		};
+		self.batch.push((id, texture));
+	}
+
+	pub fn take_batch(&mut self) -> Vec<(i32, Texture)> {
+		std::mem::take(&mut self.batch)
+	}
+
+	pub fn submitted(&mut self, fence: VkwFence, batch: Vec<(i32, Texture)>) {
+		self.in_flight.push((fence, batch));
+	}
+
+	// also true while a resident texture waits for the set rebuild
+	pub fn uploads_pending(&self) -> bool {
+		!self.batch.is_empty() || !self.in_flight.is_empty() || self.dirty
+	}
+
+	pub fn wait_resident(&mut self) {
+		for (fence, _) in self.in_flight.iter() {
+			fence.wait(None).unwrap();
+		}
+		self.commit_resident();
+	}
+
+	// batches finish in submission order
+	pub fn commit_resident(&mut self) {
+		while let Some((fence, _)) = self.in_flight.first() {
+			if !fence.is_signaled().unwrap_or(false) {
+				break;
+			}
+			let (_, batch) = self.in_flight.remove(0);
+			for (outer, mut texture) in batch {
+				// removed before it became resident
+				let slot = match self.mapper.borrow().get_held(outer) {
+					Some(slot) => slot as usize,
+					None => continue,
+				};
+				texture.last_used = self.frame;
+				self.set_texture(slot, Some(texture));
+			}
+		}
+	}
+
+	fn set_texture(&mut self, slot: usize, texture: Option<Texture>) {
+		if self.textures.len() <= slot {
+			self.textures.resize(slot + 1, None);
+		}
+		if let Some(old) = self.textures[slot].as_ref() {
+			self.memory -= old.memory;
+		}
+		if let Some(new) = texture.as_ref() {
+			self.memory += new.memory;
 		}
-		let image_view = create_image_view(image, memalloc, builder);
-		self.mapper.insert(id, self.id_alloc);
-		self.id_alloc += 1;
-		self.image_views.push(image_view);
+		self.textures[slot] = texture;
 		self.dirty = true;
 	}
 
-	pub fn tex_len(&mut self) -> (usize, HashMap<i32, i32>) {
-		let update_mapper = self.gc();
-		(self.image_views.len(), update_mapper)
+	pub fn memory(&self) -> u64 {
+		self.memory
 	}
 
+	// mark slots drawn in the current frame
+	pub fn touch(&mut self, slots: &[i32]) {
+		for slot in slots {
+			let texture = self.textures.get_mut(*slot as usize);
+			if let Some(Some(texture)) = texture {
+				texture.last_used = self.frame;
+			}
+		}
+	}
+
+	// called once per frame, textures drawn in the previous frame are kept
+	// even if still over budget
+	pub fn evict(&mut self) {
+		self.frame += 1;
+		let budget = match self.budget {
+			Some(budget) if self.memory > budget => budget,
+			_ => return,
+		};
+		let mut candidates: Vec<_> = self
+			.mapper
+			.borrow()
+			.mapper
+			.iter()
+			.filter_map(|(outer, slot)| {
+				let texture = self.texture(*slot)?;
+				if !texture.evictable || texture.last_used + 1 >= self.frame {
+					return None;
+				}
+				Some((texture.last_used, *outer, *slot as usize))
+			})
+			.collect();
+		candidates.sort_unstable();
+		for (_, outer, slot) in candidates {
+			if self.memory <= budget {
+				break;
+			}
+			self.set_texture(slot, None);
+			self.evicted.push(outer);
+		}
+	}
+
+	// latest upload of outer, even if not resident yet
+	fn latest(&self, outer: i32) -> Option<&Texture> {
+		let batches = self.in_flight.iter().map(|(_, x)| x);
+		std::iter::once(&self.batch)
+			.chain(batches.rev())
+			.find_map(|x| x.iter().rev().find(|(id, _)| *id == outer))
+			.map(|(_, texture)| texture)
+			.or_else(|| self.get(outer))
+	}
+
+	// recorded after the uploads of the same batch,
+	// the descriptor set and vertices are untouched
+	pub fn update_region(
+		&mut self,
+		outer: i32,
+		offset: [u32; 2],
+		data: Teximg,
+		memalloc: &VkwMemAlloc,
+		builder: &mut VkwCommandBuilder,
+	) {
+		let texture = match self.latest(outer) {
+			Some(texture) => texture,
+			None => {
+				eprintln!("ERROR: update of unknown texture {}", outer);
+				return;
+			}
+		};
+		let image = match texture.image.as_ref() {
+			Some(image) => image.clone(),
+			None => {
+				eprintln!("ERROR: texture {} is not dynamic", outer);
+				return;
+			}
+		};
+		if data.color != texture.color {
+			eprintln!("ERROR: update of texture {}: format mismatch", outer);
+			return;
+		}
+		let fits = (0..2).all(|i| {
+			data.dim[i] > 0
+				&& offset[i]
+					.checked_add(data.dim[i])
+					.map_or(false, |end| end <= texture.dim[i])
+		});
+		let [w, h] = data.dim.map(|x| x as usize);
+		let len = w * h * data.channels();
+		if !fits || data.data.len() != len {
+			eprintln!("ERROR: update of texture {}: bad region", outer);
+			return;
+		}
+		let copy = region_copy(memalloc, image, offset, data);
+		builder.copy_buffer_to_image(copy).unwrap();
+	}
+
+	// models using the id draw the placeholder until it is uploaded
+	// again into the same slot, the slot is reused once they are gone
 	pub fn remove(&mut self, outer: i32) {
-		assert!(outer >= 0);
-		let inner = self.mapper.remove(&outer).unwrap();
-		self.dirty = true;
-		self.remove_list.push(inner);
+		if outer < 0 || !self.free(outer) {
+			eprintln!("ERROR: remove of unknown texture {}", outer);
+		}
 	}
 
-	pub fn get_dirty(&mut self) -> bool {
-		self.dirty
+	fn free(&mut self, outer: i32) -> bool {
+		let slot = match self.mapper.borrow_mut().unhold(outer) {
+			Some(slot) => slot,
+			None => return false,
+		};
+		self.set_texture(slot as usize, None);
+		true
 	}
 
-	fn gc(&mut self) -> HashMap<i32, i32> {
-		let mut new_mapper: HashMap<i32, i32> = HashMap::new();
-		let mut update_mapper = HashMap::new();
-		let mut new_views = Vec::new();
-		for (outer, inner) in self.mapper.iter() {
-			if self.remove_list.iter().any(|x| x == inner) {
-				continue;
+	// the slot is reserved now, upload with the id of the ref
+	pub fn reserve(&mut self) -> TextureRef {
+		let id = self.auto_id;
+		self.auto_id += 1;
+		if self.mapper.borrow_mut().hold(id).is_none() {
+			eprintln!("ERROR: texture array is full");
+		}
+		let handle = TextureRef::new(id);
+		self.handles.push(handle.clone());
+		handle
+	}
+
+	// sprite ids resolve to a region of the page texture
+	pub fn add_sprites(&mut self, page: i32, sprites: Vec<(i32, [f32; 4])>) {
+		self.mapper.borrow_mut().add_sprites(page, sprites);
+	}
+
+	// remove textures whose refs are all dropped,
+	// models still using them keep the slot on the placeholder
+	pub fn gc(&mut self) {
+		for handle in std::mem::take(&mut self.handles).into_iter() {
+			if !handle.dropped() {
+				self.handles.push(handle);
+			} else {
+				self.free(handle.id());
 			}
-			update_mapper.insert(*inner, new_views.len() as i32);
-			new_mapper.insert(*outer, new_views.len() as i32);
-			new_views.push(self.image_views[*inner as usize].clone());
 		}
-		self.remove_list.clear();
-		self.mapper = new_mapper;
-		self.image_views = new_views;
-		self.dirty = false;
-		self.id_alloc = self.image_views.len() as i32;
-		update_mapper
 	}
 
-	// NOTE: gc is called in tex_len, not called here!
+	fn get(&self, outer: i32) -> Option<&Texture> {
+		let slot = self.mapper.borrow().get(outer)?;
+		self.texture(slot)
+	}
+
+	pub fn info(&self, outer: i32) -> Option<TexInfo> {
+		let texture = self.get(outer)?;
+		let image = texture.view.image();
+		let format = image.format();
+		Some(TexInfo {
+			dim: texture.dim,
+			format,
+			color: texture.color,
+			srgb: format.type_color() == Some(NumericType::SRGB),
+			mip_levels: image.mip_levels(),
+			dynamic: texture.image.is_some(),
+			sampler: texture.sampler,
+			memory: texture.memory,
+		})
+	}
+
+	pub fn image(&self, outer: i32) -> Option<Arc<dyn ImageAccess>> {
+		Some(self.get(outer)?.view.image())
+	}
+
+	// loaded textures, internal ids excluded
+	pub fn ids(&self) -> Vec<i32> {
+		let mut ids: Vec<_> = self
+			.mapper
+			.borrow()
+			.mapper
+			.keys()
+			.copied()
+			.filter(|x| *x >= 0 && self.get(*x).is_some())
+			.collect();
+		ids.sort_unstable();
+		ids
+	}
+
+	fn texture(&self, slot: i32) -> Option<&Texture> {
+		self.textures.get(slot as usize)?.as_ref()
+	}
+
+	// new or reused slots must be bound before models draw with them,
+	// other changes are batched for REBUILD_INTERVAL frames
+	pub fn needs_rebuild(&self) -> bool {
+		let (frame, len) = match self.built {
+			Some(x) => x,
+			None => return true,
+		};
+		let slots = self.mapper.borrow();
+		slots.reused
+			|| slots.slots.len() > len
+			|| self.dirty && self.frame >= frame + REBUILD_INTERVAL
+	}
+
+	// the layout has a fixed count, only the set is rewritten.
+	// vulkano has no partially bound descriptors, so empty slots
+	// are bound to the placeholder
 	pub fn compile_set(
 		&mut self,
 		device: VkwDevice,
 		dstalloc: VkwDstAlloc,
 		layout: VkwTexLayout,
 	) -> Option<VkwTextureSet> {
-		let iter: Vec<_> = self
-			.image_views
-			.iter()
-			.cloned()
-			.map(|view| {
-				let sampler =
-					Sampler::new(device.clone(), SamplerCreateInfo::default())
-						.unwrap();
-				(view as _, sampler)
+		let mut slots = self.mapper.borrow_mut();
+		let textures = &self.textures;
+		let get = |slot: i32| textures.get(slot as usize)?.as_ref();
+		let placeholder = slots.get(PLACEHOLDER).and_then(get)?;
+		let samplers = &mut self.samplers;
+		let iter: Vec<_> = (0..slots.slots.len() as i32)
+			.map(|slot| {
+				let texture = get(slot).unwrap_or(placeholder);
+				let sampler = samplers.get(device.clone(), &texture.sampler);
+				(texture.view.clone() as _, sampler)
 			})
 			.collect();
-		if iter.is_empty() {
-			return None;
-		}
+		slots.reused = false;
+		self.dirty = false;
+		self.built = Some((self.frame, iter.len()));
 
 		Some(
 			PersistentDescriptorSet::new_variable(
@@ -170,3 +804,97 @@ impl Texman {
 		)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::Slots;
+
+	#[test]
+	fn removed_id_keeps_slot_while_used() {
+		let mut slots = Slots::new(4);
+		let slot = slots.hold(1).unwrap();
+		slots.acquire(&[slot]);
+		assert_eq!(slots.unhold(1), Some(slot));
+		assert_eq!(slots.get(1), Some(slot));
+		assert_eq!(slots.get_held(1), None);
+		// a new id must not take the slot
+		assert_ne!(slots.alloc(2), Some(slot));
+	}
+
+	#[test]
+	fn reupload_reuses_slot() {
+		let mut slots = Slots::new(4);
+		let slot = slots.hold(1).unwrap();
+		slots.acquire(&[slot]);
+		slots.unhold(1);
+		assert_eq!(slots.hold(1), Some(slot));
+		assert_eq!(slots.get_held(1), Some(slot));
+	}
+
+	#[test]
+	fn slot_freed_when_unheld_and_unused() {
+		let mut slots = Slots::new(4);
+		let slot = slots.hold(1).unwrap();
+		slots.acquire(&[slot]);
+		slots.acquire(&[slot]);
+		slots.unhold(1);
+		slots.release(&[slot]);
+		assert_eq!(slots.get(1), Some(slot));
+		slots.release(&[slot]);
+		assert_eq!(slots.get(1), None);
+		assert!(!slots.reused);
+		assert_eq!(slots.alloc(2), Some(slot));
+		assert!(slots.reused);
+
+		// in the other order too
+		slots.acquire(&[slot]);
+		slots.hold(2);
+		slots.release(&[slot]);
+		assert_eq!(slots.get(2), Some(slot));
+		slots.unhold(2);
+		assert_eq!(slots.get(2), None);
+	}
+
+	#[test]
+	fn unused_alloc_is_kept_until_released() {
+		let mut slots = Slots::new(4);
+		let slot = slots.alloc(1).unwrap();
+		assert_eq!(slots.get_held(1), None);
+		assert_eq!(slots.unhold(1), None);
+		slots.acquire(&[slot]);
+		slots.release(&[slot]);
+		assert_eq!(slots.get(1), None);
+	}
+
+	#[test]
+	fn full_array_returns_none() {
+		let mut slots = Slots::new(2);
+		assert_eq!(slots.hold(1), Some(0));
+		assert_eq!(slots.hold(2), Some(1));
+		assert_eq!(slots.hold(3), None);
+		assert_eq!(slots.alloc(3), None);
+		// known ids still resolve
+		assert_eq!(slots.alloc(2), Some(1));
+		slots.unhold(1);
+		assert_eq!(slots.hold(3), Some(0));
+	}
+
+	#[test]
+	fn unhold_drops_sprites_of_page() {
+		let mut slots = Slots::new(4);
+		let rect = [0.0, 0.0, 0.5, 0.5];
+		slots.hold(1);
+		slots.hold(2);
+		slots.add_sprites(1, vec![(10, rect), (11, rect)]);
+		slots.add_sprites(2, vec![(12, rect)]);
+		// moved to a later page
+		slots.add_sprites(2, vec![(11, rect), (12, rect)]);
+		slots.unhold(1);
+		assert_eq!(slots.sprite(10), None);
+		assert_eq!(slots.sprite(11), Some((2, rect)));
+		assert_eq!(slots.sprite(12), Some((2, rect)));
+		slots.unhold(2);
+		assert_eq!(slots.sprite(11), None);
+		assert_eq!(slots.sprite(12), None);
+	}
+}
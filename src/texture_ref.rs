@@ -0,0 +1,23 @@
+use std::rc::Rc;
+
+// texture with an automatic id, removed once every clone is dropped.
+// the id can be used as face layer before the texture is uploaded,
+// its slot lives until both the refs and the models using it are gone
+#[derive(Clone, Debug)]
+pub struct TextureRef {
+	id: Rc<i32>,
+}
+
+impl TextureRef {
+	pub(crate) fn new(id: i32) -> Self {
+		Self { id: Rc::new(id) }
+	}
+
+	pub fn id(&self) -> i32 {
+		*self.id
+	}
+
+	pub fn dropped(&self) -> bool {
+		Rc::strong_count(&self.id) <= 1
+	}
+}
@@ -7,5 +7,7 @@ pub struct VertexTex {
 	pub color: [f32; 4],
 	pub tex_coord: [f32; 2],
 	pub tex_layer: i32,
+	// index into cmodel::Model::vs, for per vertex gpu data
+	pub vid: u32,
 }
-vulkano::impl_vertex!(VertexTex, pos, color, tex_coord, tex_layer);
+vulkano::impl_vertex!(VertexTex, pos, color, tex_coord, tex_layer, vid);
@@ -0,0 +1,94 @@
+// shader hot reload for development, files are polled by mtime
+// paths ending in .spv are loaded as is, others are compiled as glsl
+// which needs the glsl feature, naga does not support nonuniformEXT
+// or runtime sized arrays so the builtin shaders reload from .spv only
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::shaderman::{ShaderError, ShaderId};
+
+struct Watch {
+	id: ShaderId,
+	paths: [PathBuf; 2],
+	mtimes: [Option<SystemTime>; 2],
+}
+
+#[derive(Default)]
+pub struct Watcher {
+	watches: Vec<Watch>,
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+#[cfg(feature = "glsl")]
+fn compile_glsl(source: &str, vertex: bool) -> Result<Vec<u8>, ShaderError> {
+	use naga::back::spv;
+	use naga::front::glsl::{Options, Parser};
+	use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+	let stage = if vertex {
+		naga::ShaderStage::Vertex
+	} else {
+		naga::ShaderStage::Fragment
+	};
+	let module = Parser::default()
+		.parse(&Options::from(stage), source)
+		.map_err(|e| ShaderError::Glsl(format!("{:?}", e)))?;
+	let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+		.validate(&module)
+		.map_err(|e| ShaderError::Glsl(e.to_string()))?;
+	let words = spv::write_vec(&module, &info, &spv::Options::default(), None)
+		.map_err(|e| ShaderError::Glsl(e.to_string()))?;
+	Ok(bytemuck::cast_slice(&words).to_vec())
+}
+
+#[cfg(not(feature = "glsl"))]
+fn compile_glsl(_source: &str, _vertex: bool) -> Result<Vec<u8>, ShaderError> {
+	Err(ShaderError::Glsl("built without glsl feature".to_string()))
+}
+
+pub fn load_spirv(path: &Path, vertex: bool) -> Result<Vec<u8>, ShaderError> {
+	if path.extension().map_or(false, |x| x == "spv") {
+		std::fs::read(path).map_err(ShaderError::Io)
+	} else {
+		let source = std::fs::read_to_string(path).map_err(ShaderError::Io)?;
+		compile_glsl(&source, vertex)
+	}
+}
+
+impl Watcher {
+	pub fn watch(&mut self, id: ShaderId, vs: PathBuf, fs: PathBuf) {
+		self.watches.retain(|x| x.id != id);
+		let mtimes = [mtime(&vs), mtime(&fs)];
+		self.watches.push(Watch {
+			id,
+			paths: [vs, fs],
+			mtimes,
+		});
+	}
+
+	pub fn unwatch(&mut self, id: ShaderId) {
+		self.watches.retain(|x| x.id != id);
+	}
+
+	// spirv of changed shader pairs
+	pub fn poll(
+		&mut self,
+	) -> Vec<(ShaderId, Result<(Vec<u8>, Vec<u8>), ShaderError>)> {
+		let mut result = Vec::new();
+		for watch in self.watches.iter_mut() {
+			let mtimes = [mtime(&watch.paths[0]), mtime(&watch.paths[1])];
+			if mtimes == watch.mtimes {
+				continue;
+			}
+			watch.mtimes = mtimes;
+			let spirv = load_spirv(&watch.paths[0], true).and_then(|vs| {
+				Ok((vs, load_spirv(&watch.paths[1], false)?))
+			});
+			result.push((watch.id, spirv));
+		}
+		result
+	}
+}